@@ -0,0 +1,68 @@
+//! # Tournament
+//! Plays tic-tac-toe matches between searches with different numbers of steps per
+//! move, using the [chameleon::tournament] module.
+//!
+//! ```text
+//! cargo run --release --example tournament -- --steps 100,1600 --games 200 --sprt
+//! ```
+//!
+//! Options:
+//! - `--steps a,b,...`: number of steps per move of each entrant (default `100,1600`).
+//! - `--games n`: number of games of each match (default 100).
+//! - `--threads n`: number of games played in parallel (default: all cores).
+//! - `--alphabeta depth`: adds an alpha-beta entrant searching to the given depth.
+//! - `--sprt`: stops matches early using an SPRT between 0 and 10 Elo.
+
+use chameleon::{
+    alphabeta::AlphaBetaConfig,
+    mcts::SearchConfig,
+    time::Clock,
+    tournament::{Entrant, Sprt, Tournament, TournamentConfig},
+};
+
+mod common;
+use common::TicTacToe;
+
+pub fn main() {
+    let mut config = TournamentConfig::default();
+    let mut steps = vec![100, 1600];
+    let mut alphabeta = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| panic!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--steps" => {
+                steps = value()
+                    .split(',')
+                    .map(|s| s.parse().expect("invalid number of steps"))
+                    .collect()
+            }
+            "--games" => config.games = value().parse().expect("invalid number of games"),
+            "--threads" => config.threads = value().parse().expect("invalid number of threads"),
+            "--alphabeta" => alphabeta = Some(value().parse().expect("invalid depth")),
+            "--sprt" => config.sprt = Some(Sprt::default()),
+            arg => panic!("unknown argument {arg}"),
+        }
+    }
+
+    let mut entrants: Vec<_> = steps
+        .iter()
+        .map(|&steps| {
+            Entrant::mcts(format!("mcts-{steps}"), SearchConfig::default())
+                .with_clock(Clock::Iterations(steps))
+        })
+        .collect();
+    if let Some(depth) = alphabeta {
+        let config = AlphaBetaConfig {
+            max_depth: Some(depth),
+            ..Default::default()
+        };
+        entrants.push(Entrant::alphabeta(format!("alphabeta-{depth}"), config));
+    }
+    let tournament = Tournament::new(config, entrants);
+    println!("{}", tournament.run(TicTacToe::new));
+}
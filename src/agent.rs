@@ -0,0 +1,505 @@
+//! # Agents
+//! A common interface for anything that plays games: searches, simple baselines,
+//! scripted opponents and humans.
+//!
+//! [play_match] drives a game between agents, keeping track of their clocks. Agents
+//! are seated in the order in which their players first move: the first agent plays
+//! for the player to move in the initial state, the second for the next player to
+//! move, and so on.
+
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use std::{
+    fmt::Display,
+    io::{self, BufRead, Write},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    alphabeta::AlphaBeta,
+    book::OpeningBook,
+    evaluator::Evaluator,
+    game::{ExactUtility, Game, Utility},
+    mcts::{MonteCarloTree, SearchObserver},
+    time::{Clock, TimeManager},
+};
+
+/// A player of a game.
+pub trait Agent<G: Game> {
+    /// Chooses the action to play in the given state, which has legal actions. The
+    /// state must be left as it was given.
+    fn select_action(&mut self, state: &mut G, clock: &Clock) -> G::Action;
+
+    /// Called after every action played in the game, including the agent's own.
+    #[allow(unused_variables)]
+    fn observe(&mut self, action: &G::Action) {}
+}
+
+/// Searches with the tree, using the default [TimeManager] under clock-based time
+/// controls. Since nodes are keyed by the hash of their state, the statistics of
+/// the previous searches are reused from one move to the next. The first legal
+/// action is played if the search found none, for instance without iterations.
+impl<G: Game, O: SearchObserver<G>, R: RngCore> Agent<G> for MonteCarloTree<G, O, R> {
+    fn select_action(&mut self, state: &mut G, clock: &Clock) -> G::Action {
+        self.search(state, clock, &TimeManager::default())
+            .or_else(|| state.actions().into_iter().next())
+            .expect("agents should only play in states with legal actions")
+    }
+}
+
+/// Searches with iterative deepening, using the default [TimeManager] under
+/// clock-based time controls. The transposition table is kept from one move to the
+/// next. The first legal action is played if the search found none.
+impl<G: Game> Agent<G> for AlphaBeta<G> {
+    fn select_action(&mut self, state: &mut G, clock: &Clock) -> G::Action {
+        self.search(state, clock, &TimeManager::default())
+            .or_else(|| state.actions().into_iter().next())
+            .expect("agents should only play in states with legal actions")
+    }
+}
+
+/// Plays uniformly at random.
+pub struct RandomAgent<R: RngCore = StdRng> {
+    rng: R,
+}
+impl RandomAgent {
+    /// Creates a random agent seeded from the operating system's entropy.
+    pub fn new() -> Self {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    /// Creates a random agent with a reproducible seed.
+    pub fn seeded(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+}
+impl Default for RandomAgent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<R: RngCore> RandomAgent<R> {
+    /// Creates a random agent using the given random number generator.
+    pub fn with_rng(rng: R) -> Self {
+        Self { rng }
+    }
+}
+impl<G: Game, R: RngCore> Agent<G> for RandomAgent<R> {
+    fn select_action(&mut self, state: &mut G, _clock: &Clock) -> G::Action {
+        let mut actions = state.actions().into_iter().collect::<Vec<_>>();
+        let i = self.rng.gen_range(0..actions.len());
+        actions.swap_remove(i)
+    }
+}
+
+/// Plays the action leading to the best state, looking a single move ahead.
+///
+/// States are valued using their utility, or the given [Evaluator] if their utility
+/// is unknown. Ties are broken at random.
+pub struct GreedyAgent<G: Game> {
+    evaluator: Option<Box<dyn Evaluator<G> + Send>>,
+    rng: StdRng,
+}
+impl<G: Game> GreedyAgent<G> {
+    /// Creates a greedy agent which only tells wins, draws and losses apart, other
+    /// states being valued as draws.
+    pub fn new() -> Self {
+        Self {
+            evaluator: None,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Sets the evaluator used to value states whose utility is unknown.
+    pub fn with_evaluator(mut self, evaluator: impl Evaluator<G> + Send + 'static) -> Self {
+        self.evaluator = Some(Box::new(evaluator));
+        self
+    }
+
+    /// Seeds the breaking of ties, making the agent reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+}
+impl<G: Game> Default for GreedyAgent<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<G: Game> Agent<G> for GreedyAgent<G> {
+    fn select_action(&mut self, state: &mut G, _clock: &Clock) -> G::Action {
+        let player = state.current_player();
+        let mut best = vec![];
+        let mut best_value = i32::MIN;
+        for action in state.actions() {
+            state.play(&action);
+            // Value of the state for its side to move.
+            let value = match state.utility() {
+                Utility::Exact(ExactUtility::Win(p)) if p == state.current_player() => {
+                    i16::MAX as i32
+                }
+                Utility::Exact(ExactUtility::Win(_)) => -(i16::MAX as i32),
+                Utility::Exact(ExactUtility::Draw) => 0,
+                Utility::Approximate(approx) => approx as i32,
+                Utility::Unknown => self
+                    .evaluator
+                    .as_mut()
+                    .map_or(0, |evaluator| evaluator.evaluate(state).value as i32),
+            };
+            let value = if state.current_player() == player {
+                value
+            } else {
+                -value
+            };
+            state.undo();
+
+            if value > best_value {
+                best_value = value;
+                best.clear();
+            }
+            if value == best_value {
+                best.push(action);
+            }
+        }
+
+        let i = self.rng.gen_range(0..best.len());
+        best.swap_remove(i)
+    }
+}
+
+/// Replays the moves of a recorded game: the agent plays the move of the list at
+/// the current ply of the game, counting the moves it observed. The same agent can
+/// thus play every seat.
+pub struct ScriptedAgent<G: Game> {
+    moves: Vec<G::Action>,
+    ply: usize,
+}
+impl<G: Game> ScriptedAgent<G> {
+    /// Creates an agent replaying the given moves, starting from the first one.
+    pub fn new(moves: Vec<G::Action>) -> Self {
+        Self { moves, ply: 0 }
+    }
+}
+impl<G: Game> Agent<G> for ScriptedAgent<G> {
+    fn select_action(&mut self, _state: &mut G, _clock: &Clock) -> G::Action {
+        self.moves
+            .get(self.ply)
+            .cloned()
+            .expect("the script should have a move for every ply")
+    }
+
+    fn observe(&mut self, _action: &G::Action) {
+        self.ply += 1;
+    }
+}
+
+/// Plays a move of an [OpeningBook] while the game is in the book, picked at random
+/// according to the weights of the moves, and lets another agent play otherwise.
+pub struct BookAgent<G: Game, A: Agent<G>> {
+    book: Arc<OpeningBook<G>>,
+    agent: A,
+    rng: StdRng,
+}
+impl<G: Game, A: Agent<G>> BookAgent<G, A> {
+    /// Creates an agent playing from the book, and with the given agent out of it.
+    /// The book is shared, so that several agents can use the same one.
+    pub fn new(book: Arc<OpeningBook<G>>, agent: A) -> Self {
+        Self {
+            book,
+            agent,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the choice of book moves, making the agent reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// The agent playing out of the book.
+    pub fn agent(&self) -> &A {
+        &self.agent
+    }
+}
+impl<G: Game, A: Agent<G>> Agent<G> for BookAgent<G, A> {
+    fn select_action(&mut self, state: &mut G, clock: &Clock) -> G::Action {
+        match self.book.select(state, &mut self.rng) {
+            Some(action) => action,
+            None => self.agent.select_action(state, clock),
+        }
+    }
+
+    fn observe(&mut self, action: &G::Action) {
+        self.agent.observe(action);
+    }
+}
+
+/// A human player, shown the state and prompted for actions in text. Actions are
+/// parsed with [FromStr] until a legal one is entered.
+pub struct HumanAgent<I: BufRead, W: Write> {
+    input: I,
+    output: W,
+}
+impl HumanAgent<io::StdinLock<'static>, io::Stdout> {
+    /// Creates a human player using the standard input and output.
+    pub fn stdin() -> Self {
+        Self::new(io::stdin().lock(), io::stdout())
+    }
+}
+impl<I: BufRead, W: Write> HumanAgent<I, W> {
+    /// Creates a human player reading actions from `input` and writing prompts to
+    /// `output`.
+    pub fn new(input: I, output: W) -> Self {
+        Self { input, output }
+    }
+
+    fn prompt<G: Game + Display>(&mut self, state: &G) -> io::Result<G::Action>
+    where
+        G::Action: FromStr + Display,
+    {
+        writeln!(self.output, "{state}\n")?;
+        let actions = state.actions().into_iter().collect::<Vec<_>>();
+        let list = actions
+            .iter()
+            .map(|action| action.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(self.output, "Your turn, pick an action among {list}:")?;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            match line.trim().parse::<G::Action>() {
+                Ok(action) if actions.contains(&action) => return Ok(action),
+                _ => writeln!(self.output, "{} is not a legal action:", line.trim())?,
+            }
+        }
+    }
+}
+impl<G: Game + Display, I: BufRead, W: Write> Agent<G> for HumanAgent<I, W>
+where
+    G::Action: FromStr + Display,
+{
+    fn select_action(&mut self, state: &mut G, _clock: &Clock) -> G::Action {
+        self.prompt(state)
+            .expect("failed to read the human's action")
+    }
+}
+
+/// How a match ended, see [play_match].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchEnd {
+    /// The game reached a state with a known utility.
+    Terminal,
+    /// The agent of the given seat ran out of time.
+    Timeout(usize),
+    /// The agent of the given seat played an illegal action.
+    IllegalAction(usize),
+    /// The game reached the maximum number of plies.
+    MaxPlies,
+}
+
+/// Result of a match played by [play_match].
+pub struct MatchOutcome<G: Game> {
+    /// Seat of the winner, or [None] for a draw.
+    pub winner: Option<usize>,
+    pub end: MatchEnd,
+    /// Actions played during the match.
+    pub moves: Vec<G::Action>,
+}
+
+/// Plays a match between agents from the given state until the game ends, and
+/// leaves the state at the end of the game.
+///
+/// Each seat has its own clock, starting from the one given for it and updated as
+/// its agent plays. Agents losing on time or playing illegal actions lose the game;
+/// with more than two seats, the game is then scored as a draw. Games reaching
+/// `max_plies` plies are scored as draws.
+///
+/// # Panics
+/// Panics if the game has more players than there are agents, or if the number of
+/// clocks differs from the number of agents.
+pub fn play_match<G: Game>(
+    state: &mut G,
+    agents: &mut [&mut dyn Agent<G>],
+    clocks: &[Clock],
+    max_plies: Option<u32>,
+) -> MatchOutcome<G> {
+    assert_eq!(agents.len(), clocks.len(), "every agent needs a clock");
+    let seats = agents.len();
+    let initial_clocks = clocks;
+    let mut clocks = clocks.to_vec();
+    let mut players = vec![];
+    let mut seat_of = |player: G::Player| match players.iter().position(|p| *p == player) {
+        Some(seat) => seat,
+        None => {
+            assert!(players.len() < seats, "every player needs an agent");
+            players.push(player);
+            players.len() - 1
+        }
+    };
+    let forfeit = |seat: usize| (seats == 2).then_some(1 - seat);
+
+    let mut moves = vec![];
+    let (winner, end) = loop {
+        let seat = seat_of(state.current_player());
+        match state.utility() {
+            Utility::Unknown => {}
+            Utility::Exact(ExactUtility::Win(p)) => break (Some(seat_of(p)), MatchEnd::Terminal),
+            Utility::Exact(ExactUtility::Draw) => break (None, MatchEnd::Terminal),
+            Utility::Approximate(approx) => {
+                let winner = match approx.signum() {
+                    1 => Some(seat),
+                    -1 => forfeit(seat),
+                    _ => None,
+                };
+                break (winner, MatchEnd::Terminal);
+            }
+        }
+        if max_plies.is_some_and(|max| moves.len() >= max as usize) {
+            break (None, MatchEnd::MaxPlies);
+        }
+
+        let start = Instant::now();
+        let action = agents[seat].select_action(state, &clocks[seat]);
+        if !update_clock(&mut clocks[seat], &initial_clocks[seat], start.elapsed()) {
+            break (forfeit(seat), MatchEnd::Timeout(seat));
+        }
+        if !state.actions().into_iter().any(|a| a == action) {
+            break (forfeit(seat), MatchEnd::IllegalAction(seat));
+        }
+
+        state.play(&action);
+        for agent in agents.iter_mut() {
+            agent.observe(&action);
+        }
+        moves.push(action);
+    };
+
+    MatchOutcome { winner, end, moves }
+}
+
+/// Updates a clock after a move which took the given time, returning whether the
+/// player still had time left. Time controls with a number of moves are repeated
+/// once their moves are played.
+fn update_clock(clock: &mut Clock, initial: &Clock, elapsed: Duration) -> bool {
+    match (clock, initial) {
+        (
+            Clock::Tournament {
+                remaining,
+                increment,
+                moves_to_go,
+            },
+            Clock::Tournament {
+                remaining: initial_remaining,
+                moves_to_go: initial_moves,
+                ..
+            },
+        ) => {
+            if elapsed > *remaining {
+                return false;
+            }
+            *remaining = *remaining - elapsed + *increment;
+            if let Some(moves) = moves_to_go {
+                *moves = moves.saturating_sub(1);
+                if *moves == 0 {
+                    *remaining += *initial_remaining;
+                    *moves = initial_moves.unwrap_or(1);
+                }
+            }
+            true
+        }
+        (Clock::PerMove(time), _) => elapsed <= *time,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Subtraction;
+
+    fn scripted_match(moves: Vec<u32>, max_plies: Option<u32>) -> MatchOutcome<Subtraction> {
+        let mut first = ScriptedAgent::new(moves.clone());
+        let mut second = ScriptedAgent::new(moves);
+        let clock = Clock::Iterations(1);
+        play_match(
+            &mut Subtraction::new(10),
+            &mut [&mut first, &mut second],
+            &[clock, clock],
+            max_plies,
+        )
+    }
+
+    #[test]
+    fn plays_scripted_matches() {
+        let outcome = scripted_match(vec![3, 3, 3, 1], None);
+        assert_eq!(outcome.winner, Some(1));
+        assert_eq!(outcome.end, MatchEnd::Terminal);
+        assert_eq!(outcome.moves, vec![3, 3, 3, 1]);
+
+        // Only a single token is left for the last move.
+        let outcome = scripted_match(vec![3, 3, 3, 2], None);
+        assert_eq!(outcome.winner, Some(0));
+        assert_eq!(outcome.end, MatchEnd::IllegalAction(1));
+        assert_eq!(outcome.moves, vec![3, 3, 3]);
+
+        let outcome = scripted_match(vec![3, 3, 3, 1], Some(2));
+        assert_eq!(outcome.winner, None);
+        assert_eq!(outcome.end, MatchEnd::MaxPlies);
+        assert_eq!(outcome.moves, vec![3, 3]);
+    }
+
+    #[test]
+    fn searches_without_budget_play_legal_actions() {
+        let clock = Clock::Iterations(0);
+        let mut state = Subtraction::new(10);
+        let mut mcts = MonteCarloTree::<Subtraction>::new();
+        let action = mcts.select_action(&mut state, &clock);
+        assert!(state.actions().contains(&action));
+        let mut alphabeta = AlphaBeta::new();
+        let action = alphabeta.select_action(&mut state, &clock);
+        assert!(state.actions().contains(&action));
+    }
+
+    #[test]
+    fn updates_clocks() {
+        let secs = Duration::from_secs;
+        let initial = Clock::Tournament {
+            remaining: secs(10),
+            increment: secs(1),
+            moves_to_go: Some(2),
+        };
+        let mut clock = initial;
+        assert!(update_clock(&mut clock, &initial, secs(3)));
+        assert_eq!(
+            clock,
+            Clock::Tournament {
+                remaining: secs(8),
+                increment: secs(1),
+                moves_to_go: Some(1),
+            }
+        );
+        // The time control is repeated once its moves are played.
+        assert!(update_clock(&mut clock, &initial, secs(2)));
+        assert_eq!(
+            clock,
+            Clock::Tournament {
+                remaining: secs(17),
+                increment: secs(1),
+                moves_to_go: Some(2),
+            }
+        );
+        assert!(!update_clock(&mut clock, &initial, secs(18)));
+
+        let mut clock = Clock::PerMove(secs(1));
+        assert!(update_clock(&mut clock, &Clock::PerMove(secs(1)), secs(1)));
+        assert!(!update_clock(&mut clock, &Clock::PerMove(secs(1)), secs(2)));
+    }
+}
@@ -0,0 +1,591 @@
+//! # Alpha-beta search
+//! A depth-first minimax search, an alternative to MCTS better suited to tactical
+//! games, where a single move can change the outcome and random playouts are
+//! misleading.
+//!
+//! [AlphaBeta] searches with iterative deepening: the game tree is searched one ply
+//! deeper on every iteration until the time is up. Results are kept in a
+//! transposition table, keyed by [Game::hash], to skip positions already searched
+//! deep enough and to search the best action found earlier for a position first.
+//! The other actions are ordered by an optional [MoveOrdering] hook, then by the
+//! history heuristic. Leaves are valued with an [Evaluator], or as draws without one.
+//!
+//! Searches use the same [Clock] and [TimeManager] as
+//! [MonteCarloTree::search](crate::mcts::MonteCarloTree::search), and both engines
+//! implement [Agent](crate::agent::Agent), so that the engine can be picked per
+//! game.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    evaluator::Evaluator,
+    game::{ExactUtility, Game, Utility},
+    time::{Clock, TimeManager},
+};
+
+/// Score of a win on the current ply. Wins in `n` plies are scored `WIN - n`, and
+/// losses in `n` plies `n - WIN`, so that faster wins and slower losses are
+/// preferred. Other scores are in the range of quantized evaluations, see
+/// [Utility::Approximate].
+pub const WIN: i32 = 1 << 24;
+// Scores beyond this are wins or losses.
+const PROVEN: i32 = WIN / 2;
+// Bound of the search window, beyond any score.
+const INFINITY: i32 = WIN + 1;
+// Number of nodes between two checks of the deadline.
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Ordering of the actions searched by [AlphaBeta]. Searching the best actions
+/// first makes for more cutoffs, and thus smaller trees.
+pub trait MoveOrdering<G: Game> {
+    /// Scores an action of the given state, actions with higher scores being
+    /// searched first.
+    fn score(&mut self, state: &G, action: &G::Action) -> i32;
+}
+
+impl<G: Game, F: FnMut(&G, &G::Action) -> i32> MoveOrdering<G> for F {
+    fn score(&mut self, state: &G, action: &G::Action) -> i32 {
+        self(state, action)
+    }
+}
+
+/// Parameters of an [AlphaBeta] search.
+#[derive(Clone, Debug)]
+pub struct AlphaBetaConfig {
+    /// Maximum depth of the search, in plies. Without it, iterative deepening goes
+    /// on until the time is up, or until the game tree is searched entirely.
+    pub max_depth: Option<u32>,
+    /// Maximum number of positions kept in the transposition table, which is
+    /// cleared once full.
+    pub table_size: usize,
+    /// Enables the history heuristic: actions causing cutoffs are remembered for
+    /// each player, and searched earlier in other positions. Requires
+    /// [Game::action_key].
+    pub history_heuristic: bool,
+}
+impl Default for AlphaBetaConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            table_size: 1 << 20,
+            history_heuristic: true,
+        }
+    }
+}
+
+/// Statistics of the last search of an [AlphaBeta].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AlphaBetaStatistics {
+    /// Depth of the last completed iteration.
+    pub depth: u32,
+    /// Score of the root for its side to move at that depth, see [WIN].
+    pub score: Option<i32>,
+    /// Number of positions searched.
+    pub nodes: u64,
+    /// Number of positions whose score was found in the transposition table.
+    pub table_hits: u64,
+    pub elapsed: Duration,
+}
+impl AlphaBetaStatistics {
+    /// Average number of positions searched per second.
+    pub fn nodes_per_second(&self) -> f32 {
+        self.nodes as f32 / self.elapsed.as_secs_f32().max(f32::EPSILON)
+    }
+}
+
+// Kind of bound a score stored in the transposition table is.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+// Result of the search of a position, kept in the transposition table.
+struct TableEntry<G: Game> {
+    depth: u32,
+    score: i32,
+    bound: Bound,
+    action: Option<G::Action>,
+    // Whether no leaf of the search was cut off by the depth, in which case the
+    // score holds at any depth.
+    complete: bool,
+}
+
+/// An iterative deepening alpha-beta searcher, parametrized by the game it is
+/// playing.
+pub struct AlphaBeta<G: Game> {
+    config: AlphaBetaConfig,
+    evaluator: Option<Box<dyn Evaluator<G> + Send>>,
+    ordering: Option<Box<dyn MoveOrdering<G> + Send>>,
+    table: HashMap<G::Hash, TableEntry<G>>,
+    // Cutoffs caused by each action, kept from one search to the next.
+    history: HashMap<(G::Player, u64), u64>,
+    stats: AlphaBetaStatistics,
+    // Limits of the current search.
+    deadline: Option<Instant>,
+    max_nodes: Option<u64>,
+    aborted: bool,
+    // Whether no leaf was cut off by the depth since this flag was last set.
+    complete: bool,
+}
+impl<G: Game> Default for AlphaBeta<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<G: Game> AlphaBeta<G> {
+    /// Constructs a searcher with an empty transposition table.
+    pub fn new() -> Self {
+        Self::with_config(AlphaBetaConfig::default())
+    }
+
+    /// Constructs a searcher using the given search parameters.
+    pub fn with_config(config: AlphaBetaConfig) -> Self {
+        Self {
+            config,
+            evaluator: None,
+            ordering: None,
+            table: HashMap::new(),
+            history: HashMap::new(),
+            stats: AlphaBetaStatistics::default(),
+            deadline: None,
+            max_nodes: None,
+            aborted: false,
+            complete: true,
+        }
+    }
+
+    /// Sets the evaluator used to value leaves whose utility is unknown.
+    pub fn with_evaluator(mut self, evaluator: impl Evaluator<G> + Send + 'static) -> Self {
+        self.evaluator = Some(Box::new(evaluator));
+        self
+    }
+
+    /// Sets the hook used to order actions.
+    pub fn with_ordering(mut self, ordering: impl MoveOrdering<G> + Send + 'static) -> Self {
+        self.ordering = Some(Box::new(ordering));
+        self
+    }
+
+    /// Returns the parameters of the search.
+    pub fn config(&self) -> &AlphaBetaConfig {
+        &self.config
+    }
+
+    /// Gives mutable access to the parameters of the search, to be changed between
+    /// searches.
+    pub fn config_mut(&mut self) -> &mut AlphaBetaConfig {
+        &mut self.config
+    }
+
+    /// Returns the statistics of the last search.
+    pub fn stats(&self) -> &AlphaBetaStatistics {
+        &self.stats
+    }
+
+    /// Forgets the results of previous searches.
+    pub fn clear(&mut self) {
+        self.table.clear();
+        self.history.clear();
+    }
+
+    /// Searches the given state for as long as the time manager allows under the
+    /// given clock, and returns the best action found.
+    ///
+    /// Iterative deepening stops once the target time is reached, unless the best
+    /// action changed on the last iteration, and iterations are aborted at the
+    /// maximum time. With a fixed number of iterations, the search stops after as
+    /// many positions. With an infinite clock, it only stops once the best action
+    /// is proven or the maximum depth is reached.
+    pub fn search(
+        &mut self,
+        state: &mut G,
+        clock: &Clock,
+        time: &TimeManager,
+    ) -> Option<G::Action> {
+        let start = Instant::now();
+        let budget = time.allocate(clock);
+        self.deadline = budget.map(|budget| start + budget.maximum);
+        self.max_nodes = match *clock {
+            Clock::Iterations(nodes) => Some(nodes),
+            _ => None,
+        };
+        self.aborted = false;
+        self.stats = AlphaBetaStatistics::default();
+        for cutoffs in self.history.values_mut() {
+            *cutoffs /= 2;
+        }
+
+        let mut best = None;
+        for depth in 1..=self.config.max_depth.unwrap_or(u32::MAX) {
+            let result = self.search_root(state, depth);
+            self.stats.elapsed = start.elapsed();
+            // Without result, the root has no action or the search was aborted
+            // before any was searched.
+            let Some((action, score)) = result else {
+                break;
+            };
+            let changed = best.as_ref() != Some(&action);
+            best = Some(action);
+            if self.aborted {
+                break;
+            }
+
+            self.stats.depth = depth;
+            self.stats.score = Some(score);
+            if score.abs() >= PROVEN || self.complete {
+                break;
+            }
+            if budget.is_some_and(|budget| self.stats.elapsed >= budget.target && !changed) {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Returns the principal variation from the given state, as found by the last
+    /// searches: the sequence of best actions kept in the transposition table.
+    pub fn principal_variation(&self, state: &mut G) -> Vec<G::Action> {
+        let mut variation = vec![];
+        let mut positions = HashSet::new();
+        while let Some(action) = self
+            .table
+            .get(&state.hash())
+            .and_then(|entry| entry.action.clone())
+        {
+            if !positions.insert(state.hash()) {
+                break;
+            }
+            state.play(&action);
+            variation.push(action);
+        }
+        for _ in 0..variation.len() {
+            state.undo();
+        }
+        variation
+    }
+
+    /// Searches the root to the given depth, returning the best action along with
+    /// its score. If the search is aborted, the best of the actions searched so far
+    /// is returned.
+    fn search_root(&mut self, state: &mut G, depth: u32) -> Option<(G::Action, i32)> {
+        let player = state.current_player();
+        let first = self
+            .table
+            .get(&state.hash())
+            .and_then(|entry| entry.action.clone());
+        self.complete = true;
+
+        let mut best: Option<(G::Action, i32)> = None;
+        let mut alpha = -INFINITY;
+        for action in self.ordered_actions(state, first) {
+            state.play(&action);
+            let score = self.child_score(state, player, depth, 1, alpha, INFINITY);
+            state.undo();
+            if self.aborted {
+                break;
+            }
+            if score > alpha {
+                alpha = score;
+                best = Some((action, score));
+            }
+        }
+
+        if let Some((action, score)) = best.as_ref().filter(|_| !self.aborted) {
+            self.store(state, depth, 0, *score, Bound::Exact, Some(action.clone()));
+        }
+        best
+    }
+
+    /// Score of a child for the player who moved to it, within the window of that
+    /// player.
+    fn child_score(
+        &mut self,
+        state: &mut G,
+        player: G::Player,
+        depth: u32,
+        ply: u32,
+        alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if state.current_player() == player {
+            self.negamax(state, depth - 1, ply, alpha, beta)
+        } else {
+            -self.negamax(state, depth - 1, ply, -beta, -alpha)
+        }
+    }
+
+    /// Searches a position to the given depth, `ply` plies away from the root, and
+    /// returns its score for the side to move within the window `[alpha, beta]`.
+    fn negamax(&mut self, state: &mut G, depth: u32, ply: u32, mut alpha: i32, beta: i32) -> i32 {
+        self.stats.nodes += 1;
+        if self.max_nodes.is_some_and(|max| self.stats.nodes > max)
+            || (self.stats.nodes.is_multiple_of(CHECK_INTERVAL)
+                && self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline))
+        {
+            self.aborted = true;
+        }
+        if self.aborted {
+            return 0;
+        }
+
+        let player = state.current_player();
+        match state.utility() {
+            Utility::Exact(ExactUtility::Win(p)) if p == player => return WIN - ply as i32,
+            Utility::Exact(ExactUtility::Win(_)) => return ply as i32 - WIN,
+            Utility::Exact(ExactUtility::Draw) => return 0,
+            Utility::Approximate(approx) => return approx as i32,
+            Utility::Unknown => {}
+        }
+        if depth == 0 {
+            self.complete = false;
+            return self
+                .evaluator
+                .as_mut()
+                .map_or(0, |evaluator| evaluator.evaluate(state).value as i32);
+        }
+
+        let mut first = None;
+        if let Some(entry) = self.table.get(&state.hash()) {
+            if entry.depth >= depth || entry.complete {
+                let score = from_table(entry.score, ply);
+                let usable = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if usable {
+                    self.stats.table_hits += 1;
+                    self.complete &= entry.complete;
+                    return score;
+                }
+            }
+            first = entry.action.clone();
+        }
+
+        // Completeness of this node's subtree, merged into the parent's afterwards.
+        let parent_complete = std::mem::replace(&mut self.complete, true);
+        let initial_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_action = None;
+        for action in self.ordered_actions(state, first) {
+            state.play(&action);
+            let score = self.child_score(state, player, depth, ply + 1, alpha, beta);
+            state.undo();
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                best_action = Some(action);
+            }
+            alpha = alpha.max(best);
+            if alpha >= beta {
+                let key = best_action
+                    .as_ref()
+                    .and_then(|action| state.action_key(action));
+                if let (true, Some(key)) = (self.config.history_heuristic, key) {
+                    *self.history.entry((player, key)).or_default() += (depth * depth) as u64;
+                }
+                break;
+            }
+        }
+        if best_action.is_none() {
+            // States without actions should have a known utility, they are scored
+            // as draws otherwise.
+            best = 0;
+        }
+
+        let bound = if best <= initial_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.store(state, depth, ply, best, bound, best_action);
+        self.complete &= parent_complete;
+        best
+    }
+
+    /// Returns the actions of a state in the order they should be searched: `first`
+    /// if any, then by the score of the ordering hook, then by history.
+    fn ordered_actions(&mut self, state: &G, first: Option<G::Action>) -> Vec<G::Action> {
+        let player = state.current_player();
+        let mut actions = state
+            .actions()
+            .into_iter()
+            .map(|action| {
+                let score = self
+                    .ordering
+                    .as_mut()
+                    .map_or(0, |ordering| ordering.score(state, &action));
+                let history = match state.action_key(&action) {
+                    Some(key) if self.config.history_heuristic => {
+                        self.history.get(&(player, key)).copied().unwrap_or(0)
+                    }
+                    _ => 0,
+                };
+                (first.as_ref() == Some(&action), score, history, action)
+            })
+            .collect::<Vec<_>>();
+        actions.sort_by_key(|&(first, score, history, _)| Reverse((first, score, history)));
+        actions.into_iter().map(|(.., action)| action).collect()
+    }
+
+    /// Stores the result of the search of a position in the transposition table.
+    fn store(
+        &mut self,
+        state: &G,
+        depth: u32,
+        ply: u32,
+        score: i32,
+        bound: Bound,
+        action: Option<G::Action>,
+    ) {
+        if self.table.len() >= self.config.table_size {
+            self.table.clear();
+        }
+        self.table.insert(
+            state.hash(),
+            TableEntry {
+                depth,
+                score: to_table(score, ply),
+                bound,
+                action,
+                complete: self.complete,
+            },
+        );
+    }
+}
+
+/// Converts a score relative to the root into one relative to the position stored,
+/// so that wins and losses keep their distance when found again at another ply.
+fn to_table(score: i32, ply: u32) -> i32 {
+    match score {
+        score if score >= PROVEN => score + ply as i32,
+        score if score <= -PROVEN => score - ply as i32,
+        score => score,
+    }
+}
+
+/// Converts a score stored in the transposition table back into one relative to
+/// the root, see [to_table].
+fn from_table(score: i32, ply: u32) -> i32 {
+    match score {
+        score if score >= PROVEN => score - ply as i32,
+        score if score <= -PROVEN => score + ply as i32,
+        score => score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Subtraction;
+
+    /// Plain minimax score of a state for its side to move, see [WIN].
+    fn minimax(state: &mut Subtraction, ply: u32) -> i32 {
+        let player = state.current_player();
+        match state.utility() {
+            Utility::Exact(ExactUtility::Win(p)) if p == player => return WIN - ply as i32,
+            Utility::Exact(ExactUtility::Win(_)) => return ply as i32 - WIN,
+            _ => {}
+        }
+        let mut best = -INFINITY;
+        for action in state.actions() {
+            state.play(&action);
+            best = best.max(-minimax(state, ply + 1));
+            state.undo();
+        }
+        best
+    }
+
+    fn entry(score: i32, bound: Bound) -> TableEntry<Subtraction> {
+        TableEntry {
+            depth: 10,
+            score,
+            bound,
+            action: None,
+            complete: false,
+        }
+    }
+
+    #[test]
+    fn table_scores_keep_mate_distances() {
+        assert_eq!(to_table(WIN - 5, 3), WIN - 2);
+        assert_eq!(to_table(5 - WIN, 3), 2 - WIN);
+        assert_eq!(to_table(100, 3), 100);
+        for score in [WIN - 5, 5 - WIN, 100, -100] {
+            assert_eq!(from_table(to_table(score, 3), 3), score);
+        }
+
+        // A win in 2 plies stored at ply 3 is a win in 3 plies from ply 1.
+        let mut search = AlphaBeta::new();
+        let state = Subtraction::new(10);
+        search.store(&state, 10, 3, WIN - 5, Bound::Exact, None);
+        assert_eq!(search.table[&state.hash()].score, WIN - 2);
+        let mut state = state;
+        assert_eq!(
+            search.negamax(&mut state, 1, 1, -INFINITY, INFINITY),
+            WIN - 3
+        );
+    }
+
+    #[test]
+    fn table_bounds_only_cut_outside_the_window() {
+        let mut state = Subtraction::new(10);
+        // Without evaluator, the search to depth 1 scores the state 0.
+        let mut probe = |entry, alpha, beta| {
+            let mut search = AlphaBeta::new();
+            search.table.insert(state.hash(), entry);
+            search.negamax(&mut state, 1, 0, alpha, beta)
+        };
+
+        assert_eq!(probe(entry(100, Bound::Exact), -200, 200), 100);
+        assert_eq!(probe(entry(100, Bound::Lower), -200, 50), 100);
+        assert_eq!(probe(entry(100, Bound::Lower), -200, 200), 0);
+        assert_eq!(probe(entry(-100, Bound::Upper), -50, 200), -100);
+        assert_eq!(probe(entry(-100, Bound::Upper), -200, 200), 0);
+    }
+
+    #[test]
+    fn complete_searches_hold_at_any_depth() {
+        let mut search = AlphaBeta::new();
+        let mut state = Subtraction::new(10);
+        search.negamax(&mut state, 1, 0, -INFINITY, INFINITY);
+        assert!(!search.table[&state.hash()].complete);
+
+        // Every leaf of a pile of 2 tokens is terminal within 2 plies.
+        let mut state = Subtraction::new(2);
+        let score = search.negamax(&mut state, 2, 0, -INFINITY, INFINITY);
+        assert!(search.table[&state.hash()].complete);
+        let hits = search.stats.table_hits;
+        assert_eq!(search.negamax(&mut state, 5, 0, -INFINITY, INFINITY), score);
+        assert_eq!(search.stats.table_hits, hits + 1);
+    }
+
+    #[test]
+    fn matches_minimax() {
+        for pile in 1..=12 {
+            let mut state = Subtraction::new(pile);
+            let mut search = AlphaBeta::new();
+            let action = search
+                .search(&mut state, &Clock::Infinite, &TimeManager::default())
+                .unwrap();
+            let expected = minimax(&mut state, 0);
+            assert_eq!(search.stats.score, Some(expected), "pile {pile}");
+
+            state.play(&action);
+            assert_eq!(-minimax(&mut state, 1), expected, "pile {pile}");
+        }
+    }
+}
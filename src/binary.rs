@@ -0,0 +1,63 @@
+//! # Binary file formats
+//! Helpers shared by the readers and writers of the crate's little-endian file
+//! formats, which all start with four magic bytes followed by a `u32` version.
+
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+};
+
+/// Writes the magic bytes and the version of a file.
+pub(crate) fn write_header(
+    writer: &mut impl Write,
+    magic: &[u8; 4],
+    version: u32,
+) -> io::Result<()> {
+    writer.write_all(magic)?;
+    writer.write_all(&version.to_le_bytes())
+}
+
+/// Reads the magic bytes and the version of a file, failing if they are not the
+/// expected ones. `format` names the format in error messages.
+pub(crate) fn read_header(
+    reader: &mut impl Read,
+    magic: &[u8; 4],
+    version: u32,
+    format: &str,
+) -> io::Result<()> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    if &bytes != magic {
+        return Err(invalid_data(format!("invalid {format} file header")));
+    }
+    if read_u32(reader)? != version {
+        return Err(invalid_data(format!("unsupported {format} file version")));
+    }
+    Ok(())
+}
+
+pub(crate) fn invalid_data(error: impl Into<Box<dyn Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+pub(crate) fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(feature = "nn")]
+pub(crate) fn read_f32s(reader: &mut impl Read, count: usize) -> io::Result<Vec<f32>> {
+    let mut bytes = vec![0u8; count * 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
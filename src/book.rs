@@ -0,0 +1,461 @@
+//! # Opening books
+//! An [OpeningBook] maps the early positions of a game to weighted moves, so that
+//! agents can play them instantly instead of searching, and vary their openings by
+//! picking moves at random according to their weights.
+//!
+//! Books are built from the analysis of deep [MonteCarloTree] searches, using
+//! drop-out expansion: every position of the book has a priority, the sum of a
+//! penalty per ply and of the value lost by the moves leading to it compared to the
+//! best ones. The position with the lowest priority is searched next, so the book
+//! goes deeper along the main lines while still covering reasonable alternatives.
+//!
+//! ## File format
+//! Positions are stored as the sequence of moves leading to them, actions being
+//! given as their index in [Game::actions], so reading a book replays the moves from
+//! the initial state. The format is little-endian:
+//! - the magic bytes `CHOB` followed by the format version as a `u32` (currently 1).
+//! - the number of positions as a `u32`.
+//! - for each position, the number of moves leading to it as a `u32` followed by
+//!   their indices as `u32`s, then the number of book moves as a `u32`, each given
+//!   as its index (`u32`) and its weight (`f32`).
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng, RngCore};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    binary::{invalid_data, read_header, read_u32, write_header},
+    game::{ExactUtility, Game},
+    mcts::{ActionAnalysis, MonteCarloTree, SearchObserver},
+    time::{Clock, TimeManager},
+};
+
+const MAGIC: &[u8; 4] = b"CHOB";
+const VERSION: u32 = 1;
+
+/// Parameters of the construction of an [OpeningBook].
+#[derive(Clone, Debug)]
+pub struct BookConfig {
+    /// Time control of the search of each position.
+    pub clock: Clock,
+    /// Maximum number of positions of the book.
+    pub max_positions: usize,
+    /// Maximum number of plies of the positions of the book.
+    pub max_plies: u32,
+    /// Share of the visits of the most visited action that other actions need to
+    /// be kept in the book.
+    pub min_share: f32,
+    /// Priority added on every ply by drop-out expansion, in units of value (between
+    /// -1 and 1). Lower penalties make for deeper books.
+    pub dropout_penalty: f32,
+}
+impl Default for BookConfig {
+    fn default() -> Self {
+        Self {
+            clock: Clock::Iterations(10_000),
+            max_positions: 1000,
+            max_plies: 12,
+            min_share: 0.1,
+            dropout_penalty: 0.1,
+        }
+    }
+}
+
+/// A move of an [OpeningBook].
+pub struct BookMove<G: Game> {
+    pub action: G::Action,
+    /// Weight of the move when picking one at random, its number of visits when
+    /// the book was built.
+    pub weight: f32,
+}
+
+// A position of the book.
+struct BookEntry<G: Game> {
+    // Indices of the actions leading to the position from the initial state.
+    path: Vec<u32>,
+    moves: Vec<BookMove<G>>,
+    // Indices of the moves among the actions of the position.
+    indices: Vec<u32>,
+}
+
+/// Weighted moves for the early positions of a game, keyed by [Game::hash].
+pub struct OpeningBook<G: Game> {
+    entries: HashMap<G::Hash, BookEntry<G>>,
+}
+impl<G: Game> Default for OpeningBook<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<G: Game> OpeningBook<G> {
+    /// Creates an empty book.
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Builds a book from the given initial state, analysing positions with the
+    /// given tree by drop-out expansion, see the [module documentation](self).
+    pub fn build<O: SearchObserver<G>, R: RngCore>(
+        tree: &mut MonteCarloTree<G, O, R>,
+        root: &mut G,
+        config: &BookConfig,
+    ) -> Self {
+        let mut book = Self::new();
+        let time = TimeManager::default();
+        // Positions left to search, along with their priority.
+        let mut frontier = vec![(0f32, vec![])];
+
+        while book.len() < config.max_positions {
+            let Some(next) = frontier
+                .iter()
+                .enumerate()
+                .min_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b))
+                .map(|(i, _)| i)
+            else {
+                break;
+            };
+            let (priority, path) = frontier.swap_remove(next);
+            let Some(actions) = replay(root, &path) else {
+                continue;
+            };
+            if actions.is_empty() || book.entries.contains_key(&root.hash()) {
+                undo(root, &path);
+                continue;
+            }
+
+            tree.search(root, &config.clock, &time);
+            let analysis = tree.analysis(root);
+            let value = |action: &ActionAnalysis<G>| match action.proven {
+                Some(ExactUtility::Win(p)) if p == root.current_player() => 1f32,
+                Some(ExactUtility::Win(_)) => -1f32,
+                Some(ExactUtility::Draw) => 0f32,
+                None => action.value.unwrap_or(-1f32),
+            };
+            let won = analysis
+                .actions
+                .iter()
+                .any(|action| action.proven.is_some() && value(action) == 1f32);
+            let best_value = analysis.actions.iter().map(value).fold(-1f32, f32::max);
+            let max_visits = analysis.actions.iter().map(|a| a.visits).max().unwrap_or(0);
+
+            let mut entry = BookEntry {
+                path: path.clone(),
+                moves: vec![],
+                indices: vec![],
+            };
+            for action in &analysis.actions {
+                let value = value(action);
+                // Only proven wins are kept when there are some, and proven losses
+                // never are.
+                let kept = match action.proven {
+                    Some(_) if won => value == 1f32,
+                    Some(_) if value == -1f32 => false,
+                    _ => {
+                        !won && action.visits > 0
+                            && action.visits as f32 >= config.min_share * max_visits as f32
+                    }
+                };
+                if !kept {
+                    continue;
+                }
+
+                let index = actions.iter().position(|a| *a == action.action).unwrap() as u32;
+                entry.moves.push(BookMove {
+                    action: action.action.clone(),
+                    weight: action.visits.max(1) as f32,
+                });
+                entry.indices.push(index);
+                if (path.len() as u32) < config.max_plies {
+                    let mut child = path.clone();
+                    child.push(index);
+                    let priority = priority + config.dropout_penalty + best_value - value;
+                    frontier.push((priority, child));
+                }
+            }
+            if !entry.moves.is_empty() {
+                book.entries.insert(root.hash(), entry);
+            }
+            undo(root, &path);
+        }
+        book
+    }
+
+    /// Number of positions of the book.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the book has no position.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the book moves of the given state, if it is in the book.
+    pub fn get(&self, state: &G) -> Option<&[BookMove<G>]> {
+        self.entries
+            .get(&state.hash())
+            .map(|entry| entry.moves.as_slice())
+    }
+
+    /// Picks a book move of the given state at random, according to the weights of
+    /// the moves.
+    pub fn select(&self, state: &G, rng: &mut impl Rng) -> Option<G::Action> {
+        let moves = self.get(state)?;
+        let weights = WeightedIndex::new(moves.iter().map(|m| m.weight)).ok()?;
+        Some(moves[weights.sample(rng)].action.clone())
+    }
+
+    /// Loads a book from a file, replaying its positions from the given initial
+    /// state.
+    pub fn load(path: impl AsRef<Path>, root: &mut G) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?), root)
+    }
+
+    /// Saves the book to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Reads a book in the format described in the [module documentation](self),
+    /// replaying its positions from the given initial state.
+    pub fn read(reader: &mut impl Read, root: &mut G) -> io::Result<Self> {
+        read_header(reader, MAGIC, VERSION, "opening book")?;
+
+        let mut book = Self::new();
+        for _ in 0..read_u32(reader)? {
+            let path = (0..read_u32(reader)?)
+                .map(|_| read_u32(reader))
+                .collect::<io::Result<Vec<_>>>()?;
+            let moves = (0..read_u32(reader)?)
+                .map(|_| Ok((read_u32(reader)?, f32::from_bits(read_u32(reader)?))))
+                .collect::<io::Result<Vec<_>>>()?;
+
+            let Some(actions) = replay(root, &path) else {
+                return Err(invalid_data("invalid opening book position"));
+            };
+            let entry = moves
+                .into_iter()
+                .map(|(index, weight)| {
+                    let action = actions.get(index as usize)?.clone();
+                    Some((BookMove { action, weight }, index))
+                })
+                .collect::<Option<Vec<_>>>();
+            let hash = root.hash();
+            undo(root, &path);
+
+            let Some(entry) = entry else {
+                return Err(invalid_data("invalid opening book move"));
+            };
+            let (moves, indices) = entry.into_iter().unzip();
+            book.entries.insert(
+                hash,
+                BookEntry {
+                    path,
+                    moves,
+                    indices,
+                },
+            );
+        }
+        Ok(book)
+    }
+
+    /// Writes the book in the format described in the [module documentation](self).
+    /// Positions are sorted by their path, so that the same book is always written
+    /// the same way.
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_header(writer, MAGIC, VERSION)?;
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        let mut entries = self.entries.values().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        for entry in entries {
+            writer.write_all(&(entry.path.len() as u32).to_le_bytes())?;
+            for index in &entry.path {
+                writer.write_all(&index.to_le_bytes())?;
+            }
+            writer.write_all(&(entry.moves.len() as u32).to_le_bytes())?;
+            for (index, m) in entry.indices.iter().zip(&entry.moves) {
+                writer.write_all(&index.to_le_bytes())?;
+                writer.write_all(&m.weight.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Plays the actions of the given indices from the state, and returns the actions
+/// of the resulting position. Returns [None], with the state left as it was, if an
+/// index is out of range.
+fn replay<G: Game>(state: &mut G, path: &[u32]) -> Option<Vec<G::Action>> {
+    for (played, &index) in path.iter().enumerate() {
+        let Some(action) = state.actions().into_iter().nth(index as usize) else {
+            undo(state, &path[..played]);
+            return None;
+        };
+        state.play(&action);
+    }
+    Some(state.actions().into_iter().collect())
+}
+
+/// Undoes the actions of a path played by [replay].
+fn undo<G: Game>(state: &mut G, path: &[u32]) {
+    for _ in path {
+        state.undo();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        mcts::SearchConfig,
+        tablebase::Enumerable,
+        testing::{Subtraction, MAX_PILE, MAX_TAKE},
+    };
+
+    const PILE: u32 = 9;
+
+    fn book() -> OpeningBook<Subtraction> {
+        let config = BookConfig {
+            clock: Clock::Iterations(2000),
+            max_positions: 8,
+            ..Default::default()
+        };
+        let mut tree = MonteCarloTree::with_config(SearchConfig {
+            seed: Some(0),
+            ..Default::default()
+        });
+        OpeningBook::build(&mut tree, &mut Subtraction::new(PILE), &config)
+    }
+
+    fn bytes(book: &OpeningBook<Subtraction>) -> Vec<u8> {
+        let mut bytes = vec![];
+        book.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn read(bytes: &[u8]) -> io::Result<OpeningBook<Subtraction>> {
+        OpeningBook::read(&mut &bytes[..], &mut Subtraction::new(PILE))
+    }
+
+    #[test]
+    fn build_keeps_winning_moves() {
+        let book = book();
+        assert!(!book.is_empty() && book.len() <= 8);
+        assert!(book.get(&Subtraction::new(PILE)).is_some());
+        for state in Subtraction::states() {
+            let Some(moves) = book.get(&state) else {
+                continue;
+            };
+            assert!(!moves.is_empty());
+            if !state.pile.is_multiple_of(MAX_TAKE + 1) {
+                for m in moves {
+                    assert!((state.pile - m.action).is_multiple_of(MAX_TAKE + 1));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn select_picks_book_moves() {
+        let book = book();
+        let state = Subtraction::new(PILE);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let action = book.select(&state, &mut rng).unwrap();
+            assert!(book.get(&state).unwrap().iter().any(|m| m.action == action));
+        }
+        assert!(book.select(&Subtraction::new(PILE + 1), &mut rng).is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        let book = book();
+        let read = read(&bytes(&book)).unwrap();
+        assert_eq!(read.len(), book.len());
+        for state in Subtraction::states() {
+            let moves = |book: &OpeningBook<Subtraction>| {
+                book.get(&state).map(|moves| {
+                    moves
+                        .iter()
+                        .map(|m| (m.action, m.weight))
+                        .collect::<Vec<_>>()
+                })
+            };
+            assert_eq!(moves(&read), moves(&book));
+        }
+    }
+
+    #[test]
+    fn writes_deterministically() {
+        // Searches too short to prove positions, so that the book has many of them.
+        let config = BookConfig {
+            clock: Clock::Iterations(5),
+            max_positions: 20,
+            ..Default::default()
+        };
+        let mut tree = MonteCarloTree::with_config(SearchConfig {
+            seed: Some(0),
+            ..Default::default()
+        });
+        let mut root = Subtraction::new(MAX_PILE);
+        let book = OpeningBook::build(&mut tree, &mut root, &config);
+        assert!(book.len() > 1);
+
+        let written = bytes(&book);
+        for _ in 0..5 {
+            let read = OpeningBook::read(&mut &written[..], &mut root).unwrap();
+            assert_eq!(bytes(&read), written);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        for (i, byte) in [(0, b'X'), (4, 2)] {
+            let mut bytes = bytes(&book());
+            bytes[i] = byte;
+            let error = read(&bytes).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let bytes = bytes(&book());
+        for len in [6, 12, bytes.len() - 1] {
+            let error = read(&bytes[..len]).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_moves() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        // A single position, the root, with a move out of range.
+        for x in [1, 0, 1, MAX_TAKE] {
+            bytes.extend(x.to_le_bytes());
+        }
+        bytes.extend(1f32.to_le_bytes());
+        let error = read(&bytes).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // A position out of the game.
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        for x in [1, 1, MAX_TAKE, 0] {
+            bytes.extend(x.to_le_bytes());
+        }
+        let error = read(&bytes).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,71 @@
+//! # Evaluators
+//! Random playouts are the most general way of estimating the value of a position,
+//! but they are also slow and noisy. When some knowledge about the game is available
+//! (handcrafted heuristics, a trained model, ...), an [Evaluator] can be used to
+//! score leaf nodes directly instead of, or alongside, playouts.
+//!
+//! Evaluators are kept separate from the [Game] trait so that the same game
+//! description can be searched with different evaluation functions.
+
+use crate::game::{ExactUtility, Game};
+
+/// The result of evaluating a game state.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Evaluation {
+    /// Quantized value of the state for the side to move, following the same
+    /// convention as [Utility::Approximate](crate::game::Utility::Approximate):
+    /// `i16::MAX` is a certain win, `i16::MIN` a certain loss.
+    pub value: i16,
+    /// Optional prior probabilities of each action, given in the same order as
+    /// [Game::actions] yields them.
+    pub priors: Option<Vec<f32>>,
+}
+impl Evaluation {
+    /// An evaluation holding only a value, without move priors.
+    pub fn value(value: i16) -> Self {
+        Self {
+            value,
+            priors: None,
+        }
+    }
+}
+
+/// Heuristic evaluation of game states.
+pub trait Evaluator<G: Game> {
+    /// Evaluates a non-terminal state.
+    fn evaluate(&mut self, state: &G) -> Evaluation;
+}
+
+impl<G: Game, F: FnMut(&G) -> Evaluation> Evaluator<G> for F {
+    fn evaluate(&mut self, state: &G) -> Evaluation {
+        self(state)
+    }
+}
+
+/// Exact knowledge of the value of game states, such as an endgame
+/// [Tablebase](crate::tablebase::Tablebase).
+pub trait Oracle<G: Game> {
+    /// Returns the exact utility of a state along with its distance to the end of
+    /// the game in plies, if it is known. The state must be left as it was given.
+    fn probe(&self, state: &mut G) -> Option<(ExactUtility<G>, u32)>;
+}
+
+impl<G: Game, F: Fn(&mut G) -> Option<(ExactUtility<G>, u32)>> Oracle<G> for F {
+    fn probe(&self, state: &mut G) -> Option<(ExactUtility<G>, u32)> {
+        self(state)
+    }
+}
+
+/// Encoding of game states and actions as fixed-size numerical inputs and outputs,
+/// as used by learned evaluators.
+pub trait TensorEncoding: Game {
+    /// Size of the tensors returned by [TensorEncoding::to_tensor].
+    const TENSOR_SIZE: usize;
+    /// Number of distinct action indices, i.e. the size of a policy over actions.
+    const POLICY_SIZE: usize;
+
+    /// Encodes the state as a flat tensor of size [TensorEncoding::TENSOR_SIZE].
+    fn to_tensor(&self) -> Vec<f32>;
+    /// Returns the index of an action in a policy of size [TensorEncoding::POLICY_SIZE].
+    fn action_index(&self, action: &Self::Action) -> usize;
+}
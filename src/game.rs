@@ -16,11 +16,7 @@ pub enum Utility<G: Game> {
 }
 impl<G: Game> Clone for Utility<G> {
     fn clone(&self) -> Self {
-        match self {
-            Self::Exact(e) => Self::Exact(*e),
-            Self::Approximate(i) => Self::Approximate(*i),
-            Self::Unknown => Self::Unknown,
-        }
+        *self
     }
 }
 impl<G: Game> Copy for Utility<G> {}
@@ -32,10 +28,7 @@ pub enum ExactUtility<G: Game> {
 }
 impl<G: Game> Clone for ExactUtility<G> {
     fn clone(&self) -> Self {
-        match self {
-            Self::Win(p) => Self::Win(*p),
-            Self::Draw => Self::Draw,
-        }
+        *self
     }
 }
 impl<G: Game> Copy for ExactUtility<G> {}
@@ -43,7 +36,7 @@ impl<G: Game> Copy for ExactUtility<G> {}
 /// The [Game] trait is meant to describe a (potentially infinite) game tree in
 /// a way that is usable by the MCTS algorithm.
 pub trait Game: Sized {
-    type Action: PartialEq + Eq;
+    type Action: Clone + PartialEq + Eq;
     type ActionsIter: IntoIterator<Item = Self::Action>;
     type Hash: Hash + Eq + PartialEq;
     type Player: Clone + Copy + PartialEq + Hash + Eq;
@@ -56,4 +49,12 @@ pub trait Game: Sized {
 
     fn utility(&self) -> Utility<Self>;
     fn hash(&self) -> Self::Hash;
+
+    /// Identifies an action independently of the state it is played in, as needed
+    /// by statistics shared between states: RAVE, the MAST and NST playout policies
+    /// and the history heuristic of alpha-beta. Games without such keys, which is
+    /// the default, search without these statistics.
+    fn action_key(&self, _action: &Self::Action) -> Option<u64> {
+        None
+    }
 }
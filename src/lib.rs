@@ -8,5 +8,19 @@
 //! - use a game description language for truly general game playing.
 //! - implement your own game logic to implement engines for specific games.
 
+pub mod agent;
+pub mod alphabeta;
+mod binary;
+pub mod book;
+pub mod evaluator;
 pub mod game;
 pub mod mcts;
+#[cfg(feature = "nn")]
+pub mod nn;
+pub mod pns;
+pub mod selfplay;
+pub mod tablebase;
+#[cfg(test)]
+mod testing;
+pub mod time;
+pub mod tournament;
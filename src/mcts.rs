@@ -9,32 +9,503 @@
 //! Since heuristic and results of past searches are needed in order to know how
 //! to traverse the tree, we need to keep said search tree entirely in memory.
 
-use rand::seq::IteratorRandom;
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use rand_distr::{Distribution, Gamma};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
-use crate::game::{ExactUtility, Game, Utility};
+use crate::{
+    evaluator::{Evaluation, Evaluator, Oracle},
+    game::{ExactUtility, Game, Utility},
+};
+
+mod analysis;
+mod background;
+mod export;
+mod gumbel;
+mod minimax;
+mod observer;
+mod playout;
+mod rave;
+mod search;
+mod stats;
+
+pub use analysis::{ActionAnalysis, Analysis};
+pub use background::SearchHandle;
+pub(crate) use export::escape_json;
+pub use export::ExportOptions;
+pub use observer::SearchObserver;
+pub use stats::SearchStatistics;
+
+/// How nodes with an [Unknown](Utility::Unknown) utility are assigned a value
+/// when they are expanded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LeafEvaluation {
+    /// Random playouts from the expanded node. The tree's [Evaluator], if any, is
+    /// only used for its priors and for implicit minimax backups, and is not
+    /// called when the search uses neither.
+    Playouts,
+    /// The [Evaluator] given to the tree, without any playout.
+    Evaluator,
+    /// A weighted average of both, `playout_weight` being the share given to
+    /// the result of playouts (between 0 and 1).
+    Mixed { playout_weight: f32 },
+}
+
+/// The formula used to balance exploration and exploitation when selecting
+/// which child of a node to search.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selection {
+    /// Upper Confidence bounds applied to Trees, the classical MCTS formula.
+    Uct { exploration: f32 },
+    /// Predictor UCT, as used by AlphaZero: exploration of each child is weighted by
+    /// its prior probability, given by the tree's [Evaluator]. Children of nodes
+    /// without priors are considered equally likely.
+    Puct { exploration: f32 },
+}
+impl Default for Selection {
+    fn default() -> Self {
+        Self::Uct {
+            exploration: 2f32.sqrt(),
+        }
+    }
+}
+
+/// The algorithm used to pick which action of the root node to search.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RootSearch {
+    /// The root is searched like any other node, using the [Selection] formula.
+    #[default]
+    Selection,
+    /// Gumbel top-k sampling of the root actions followed by Sequential Halving,
+    /// which makes a better use of small simulation budgets. Uses the priors given
+    /// by the tree's [Evaluator] if any.
+    Gumbel {
+        /// Number of root actions sampled for consideration.
+        considered: usize,
+        /// Expected number of calls to [MonteCarloTree::step] from the root.
+        simulations: u32,
+    },
+}
+
+/// Schedule of the weight given to all-moves-as-first (AMAF) values over the
+/// regular values of children during selection, see [SearchConfig::rave].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RaveSchedule {
+    /// Hand-selected schedule `β = sqrt(k / (3n + k))`, `n` being the number of
+    /// visits of the child. AMAF and regular values are equally weighted after
+    /// `k` visits.
+    Equivalence { k: f32 },
+    /// MC-RAVE schedule minimizing the mean squared error of the combined value,
+    /// `β = ñ / (n + ñ + 4b²nñ)`, `ñ` being the number of AMAF samples of the
+    /// action and `b` the assumed bias of AMAF values.
+    MinimumMse { bias: f32 },
+}
+
+/// How actions are picked during playouts.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PlayoutPolicy {
+    /// Actions are picked uniformly at random.
+    #[default]
+    Uniform,
+    /// Move-Average Sampling Technique: actions are picked according to the
+    /// average result of the playouts in which they were played.
+    Mast { selection: MoveSelection },
+    /// N-gram Selection Technique: actions are picked according to the average
+    /// result of the playouts in which they were played after the same actions,
+    /// averaged over sequences of up to `length` actions. Sequences of more than
+    /// one action are only taken into account once they were played `threshold`
+    /// times.
+    Nst {
+        length: usize,
+        threshold: u32,
+        selection: MoveSelection,
+    },
+}
+
+/// How playout policies turn the scores of actions into a choice.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MoveSelection {
+    /// Actions are sampled from a Gibbs (softmax) distribution over their scores.
+    Gibbs { temperature: f32 },
+    /// The best action is picked, except with probability `epsilon` where a random
+    /// action is picked instead.
+    EpsilonGreedy { epsilon: f32 },
+}
+
+/// Value given to playouts that are cut off before reaching a terminal state.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PlayoutCutoff {
+    /// The playout is scored as a draw.
+    #[default]
+    Draw,
+    /// The playout is scored using the tree's [Evaluator], or as a draw if the
+    /// tree has none.
+    Evaluator,
+}
+
+/// Progressive widening parameters: a node visited `n` times only considers its
+/// `⌈coefficient × n^exponent⌉` most promising children, see
+/// [SearchConfig::widening].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProgressiveWidening {
+    pub coefficient: f32,
+    /// Growth rate of the number of children, usually between 0.25 and 0.5.
+    pub exponent: f32,
+}
 
-/// A Monte-Carlo searched tree parametrized by the game it is playing.
-pub struct MonteCarloTree<G: Game> {
+/// Dirichlet noise mixed into the priors of the root node, so that searches
+/// explore moves that the priors would otherwise neglect.
+///
+/// Only affects [Selection::Puct], which is the only formula using priors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirichletNoise {
+    /// Concentration parameter of the distribution, smaller values concentrate
+    /// the noise on fewer actions.
+    pub alpha: f32,
+    /// Share of the noise in the resulting priors (between 0 and 1).
+    pub epsilon: f32,
+}
+
+/// Parameters of a [MonteCarloTree] search.
+#[derive(Clone, Debug)]
+pub struct SearchConfig {
+    /// The number of random playouts when expanding a node with unknown utility.
+    pub simulations_per_node: u32,
+    /// How unknown leaves are evaluated. Falls back to [LeafEvaluation::Playouts]
+    /// if the tree has no [Evaluator].
+    pub leaf_evaluation: LeafEvaluation,
+    /// Enables implicit minimax backups: each node also keeps the minimax value of
+    /// the evaluations in its subtree, and selection uses a weighted average of
+    /// this value and the mean result of simulations. The given weight (between
+    /// 0 and 1) is the share of the minimax value.
+    ///
+    /// Leaves are valued by the tree's [Evaluator] if any, even when playouts are
+    /// used for the mean result, and by the result of their evaluation otherwise.
+    pub implicit_minimax: Option<f32>,
+    /// Depth of a minimax search run on every leaf before its evaluation, looking
+    /// for a forced win of either player within that many plies. Leaves proven this
+    /// way are given an exact utility instead of being evaluated.
+    pub expansion_minimax: Option<u32>,
+    /// The selection formula used to traverse the tree.
+    pub selection: Selection,
+    /// The algorithm used at the root of the tree.
+    pub root_search: RootSearch,
+    /// Enables Rapid Action Value Estimation: nodes record the results of all
+    /// simulations in which each action was played by their side to move, and
+    /// selection blends these values with the regular ones following the given
+    /// schedule. Unvisited children whose action has AMAF samples are valued by
+    /// them alone: they are expanded in order of AMAF value, or given it instead of
+    /// [SearchConfig::first_play_urgency]. Requires [Game::action_key].
+    pub rave: Option<RaveSchedule>,
+    /// The policy used to pick actions during playouts. Policies other than
+    /// [PlayoutPolicy::Uniform] require [Game::action_key].
+    pub playout_policy: PlayoutPolicy,
+    /// Maximum number of plies of a playout, after which it is cut off.
+    pub max_playout_length: Option<u32>,
+    /// Enables the detection of cycles during playouts: a playout that goes back
+    /// to a position it already went through is cut off.
+    pub playout_cycle_detection: bool,
+    /// Value given to playouts that were cut off.
+    pub playout_cutoff: PlayoutCutoff,
+    /// Depth of a minimax search run at every step of playouts: actions forcing a
+    /// win within that many plies are played instead of following the playout
+    /// policy.
+    pub playout_minimax: Option<u32>,
+    /// Enables progressive widening, limiting the number of children considered
+    /// by selection depending on the number of visits of a node. Children are
+    /// ranked by their priors, or by the order of [Game::actions] if the node has
+    /// none.
+    ///
+    /// Without priors, the actions iterator is only consumed up to the limit, so
+    /// games with large or continuous action spaces can generate (or sample) their
+    /// actions lazily, in order of interest. Note that playouts and
+    /// [MonteCarloTree::best_action] still go through every action.
+    pub widening: Option<ProgressiveWidening>,
+    /// First play urgency: unvisited children are given this value (between -1
+    /// and 1) instead of being expanded before any other child is revisited.
+    pub first_play_urgency: Option<f32>,
+    /// Enables progressive bias: the prior of each child, weighted by the given
+    /// factor, is added to its value and fades away as `1 / (n + 1)`, `n` being the
+    /// number of visits of the child. Priors are given by the tree's [Evaluator].
+    pub progressive_bias: Option<f32>,
+    /// Noise added to the priors of the root node.
+    pub root_noise: Option<DirichletNoise>,
+    /// Probability of selecting a root action uniformly at random instead of
+    /// using the selection formula.
+    pub root_epsilon: Option<f32>,
+    /// First play urgency of the root node, overriding
+    /// [SearchConfig::first_play_urgency] for the root's children.
+    pub root_first_play_urgency: Option<f32>,
+    /// Seed of the random number generator of the search, used for playouts and
+    /// root perturbations. The same seed and the same calls to [MonteCarloTree::step]
+    /// give identical trees. A random seed is used if none is given.
+    ///
+    /// The generator is only seeded when the tree is created: when a tree is reused
+    /// over several moves, with or without [MonteCarloTree::retain_subtree], its
+    /// searches depend on all the previous ones.
+    pub seed: Option<u64>,
+    /// Maximum length of the principal variations reported by
+    /// [MonteCarloTree::analysis].
+    pub pv_depth: usize,
+}
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            simulations_per_node: 255,
+            leaf_evaluation: LeafEvaluation::Playouts,
+            implicit_minimax: None,
+            expansion_minimax: None,
+            selection: Selection::default(),
+            root_search: RootSearch::default(),
+            rave: None,
+            playout_policy: PlayoutPolicy::default(),
+            max_playout_length: None,
+            playout_cycle_detection: false,
+            playout_cutoff: PlayoutCutoff::default(),
+            playout_minimax: None,
+            widening: None,
+            first_play_urgency: None,
+            progressive_bias: None,
+            root_noise: None,
+            root_epsilon: None,
+            root_first_play_urgency: None,
+            seed: None,
+            pv_depth: 8,
+        }
+    }
+}
+impl SearchConfig {
+    /// Creates a random number generator seeded from [SearchConfig::seed], or from
+    /// the operating system's entropy if there is no seed.
+    pub fn rng<R: SeedableRng>(&self) -> R {
+        self.seed.map_or_else(R::from_entropy, R::seed_from_u64)
+    }
+
+    /// Returns a copy of this configuration whose seed is derived for the given
+    /// stream, so that parallel searches, such as one tree per thread, use distinct
+    /// but reproducible random numbers.
+    pub fn stream(&self, stream: u64) -> Self {
+        Self {
+            seed: self.seed.map(|seed| derive_seed(seed, stream)),
+            ..self.clone()
+        }
+    }
+}
+
+/// A Monte-Carlo searched tree parametrized by the game it is playing, and
+/// optionally by a [SearchObserver] notified of the search's progress and by its
+/// random number generator.
+pub struct MonteCarloTree<G: Game, O = (), R = StdRng> {
     nodes: HashMap<G::Hash, Arc<Mutex<MonteCarloNode<G>>>>,
 
-    // The number of random playouts when expanding a node with unknown utility.
-    simulations_per_node: u32,
+    config: SearchConfig,
+    evaluator: Option<Box<dyn Evaluator<G> + Send>>,
+    oracle: Option<Box<dyn Oracle<G> + Send>>,
+    // Noise sampled for the root node, along with the hash of said root.
+    root_noise: Option<(G::Hash, Vec<f32>)>,
+    // Sequential Halving schedule of the root, when using Gumbel search.
+    gumbel: Option<gumbel::SequentialHalving<G>>,
+    // Source of randomness of the search, see [SearchConfig::seed].
+    rng: R,
+    // AMAF statistics of the last playouts, see [MonteCarloTree::simulate].
+    playout_amaf: HashMap<(G::Player, u64), (f32, f32)>,
+    // Statistics used by the playout policy, kept from one search to the next.
+    playout_statistics: playout::PlayoutStatistics<G>,
+    observer: O,
+    stats: SearchStatistics,
+}
+impl<G: Game> Default for MonteCarloTree<G> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl<G: Game> MonteCarloTree<G> {
     /// Constructs an empty search tree.
     pub fn new() -> Self {
+        Self::with_config(SearchConfig::default())
+    }
+
+    /// Constructs an empty search tree using the given search parameters.
+    pub fn with_config(config: SearchConfig) -> Self {
         Self {
             nodes: HashMap::new(),
 
-            simulations_per_node: 255,
+            rng: config.rng(),
+            config,
+            evaluator: None,
+            oracle: None,
+            root_noise: None,
+            gumbel: None,
+            playout_amaf: HashMap::new(),
+            playout_statistics: playout::PlayoutStatistics::default(),
+            observer: (),
+            stats: SearchStatistics::default(),
+        }
+    }
+}
+impl<G: Game, O: SearchObserver<G>, R: RngCore> MonteCarloTree<G, O, R> {
+    /// Sets the observer notified of the events of the search, replacing the
+    /// previous one.
+    pub fn with_observer<P: SearchObserver<G>>(self, observer: P) -> MonteCarloTree<G, P, R> {
+        MonteCarloTree {
+            nodes: self.nodes,
+            config: self.config,
+            evaluator: self.evaluator,
+            oracle: self.oracle,
+            root_noise: self.root_noise,
+            gumbel: self.gumbel,
+            rng: self.rng,
+            playout_amaf: self.playout_amaf,
+            playout_statistics: self.playout_statistics,
+            observer,
+            stats: self.stats,
+        }
+    }
+
+    /// Replaces the random number generator of the search, which is otherwise a
+    /// [StdRng] seeded from [SearchConfig::seed]. See [SearchConfig::rng] to seed
+    /// other generators from the configuration.
+    pub fn with_rng<S: RngCore>(self, rng: S) -> MonteCarloTree<G, O, S> {
+        MonteCarloTree {
+            nodes: self.nodes,
+            config: self.config,
+            evaluator: self.evaluator,
+            oracle: self.oracle,
+            root_noise: self.root_noise,
+            gumbel: self.gumbel,
+            rng,
+            playout_amaf: self.playout_amaf,
+            playout_statistics: self.playout_statistics,
+            observer: self.observer,
+            stats: self.stats,
+        }
+    }
+
+    /// Returns the observer of this tree.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Returns the observer of this tree for modification.
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Sets the evaluator used to score leaf nodes, see [SearchConfig::leaf_evaluation].
+    pub fn with_evaluator(mut self, evaluator: impl Evaluator<G> + Send + 'static) -> Self {
+        self.evaluator = Some(Box::new(evaluator));
+        self
+    }
+
+    /// Sets the oracle probed for the exact utility of leaf nodes before they are
+    /// evaluated. The best action of states known to the oracle is also taken from
+    /// it, playing the fastest wins and the slowest losses.
+    pub fn with_oracle(mut self, oracle: impl Oracle<G> + Send + 'static) -> Self {
+        self.oracle = Some(Box::new(oracle));
+        self
+    }
+
+    /// Returns the search parameters of this tree.
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    /// Returns the search parameters of this tree for modification. Changes apply
+    /// to the following calls to [MonteCarloTree::step].
+    pub fn config_mut(&mut self) -> &mut SearchConfig {
+        &mut self.config
+    }
+
+    /// Discards the nodes which cannot be reached from the given state, keeping the
+    /// statistics of its subtree for the next searches. Returns the number of nodes
+    /// discarded.
+    ///
+    /// Since nodes are keyed by the hash of their state, the subtree of a state is
+    /// reused even without calling this; it only frees the memory of the rest. The
+    /// random number generator is not reset either, see [SearchConfig::seed].
+    pub fn retain_subtree(&mut self, state: &mut G) -> usize {
+        let mut reachable = HashSet::new();
+        self.reachable_nodes(state, &mut reachable);
+        let count = self.nodes.len();
+        self.nodes.retain(|hash, _| reachable.contains(hash));
+        count - self.nodes.len()
+    }
+
+    // Collects the hashes of the nodes reachable from the given state.
+    fn reachable_nodes(&self, state: &mut G, reachable: &mut HashSet<G::Hash>) {
+        if !self.nodes.contains_key(&state.hash()) || !reachable.insert(state.hash()) {
+            return;
+        }
+        for action in state.actions() {
+            state.play(&action);
+            self.reachable_nodes(state, reachable);
+            state.undo();
+        }
+    }
+
+    /// Returns the number of visits of each child of the given state, in the order
+    /// given by [Game::actions]. Children that were not expanded have no visits.
+    pub fn visit_counts(&self, state: &mut G) -> Vec<(G::Action, u32)> {
+        state
+            .actions()
+            .into_iter()
+            .map(|action| {
+                state.play(&action);
+                let visits = self
+                    .nodes
+                    .get(&state.hash())
+                    .map_or(0, |child| child.lock().unwrap().visits);
+                state.undo();
+                (action, visits)
+            })
+            .collect()
+    }
+
+    /// Returns the best action of a state known to the oracle: the fastest win, a
+    /// draw, or the slowest loss.
+    fn oracle_action(&self, state: &mut G) -> Option<G::Action> {
+        let oracle = self.oracle.as_ref()?;
+        oracle.probe(state)?;
+        let current_player = state.current_player();
+
+        let mut best = None;
+        for action in state.actions() {
+            state.play(&action);
+            let score = oracle
+                .probe(state)
+                .map(|(utility, distance)| match utility {
+                    ExactUtility::Win(p) if p == current_player => (2, -(distance as i64)),
+                    ExactUtility::Draw => (1, 0),
+                    ExactUtility::Win(_) => (0, distance as i64),
+                });
+            state.undo();
+            if let Some(score) = score.filter(|score| best.as_ref().is_none_or(|(s, _)| score > s))
+            {
+                best = Some((score, action));
+            }
+        }
+        best.map(|(_, action)| action)
+    }
+
+    /// Returns the exact utility of the given state, if the search proved it.
+    pub fn proven_utility(&self, state: &G) -> Option<ExactUtility<G>> {
+        match self.nodes.get(&state.hash())?.lock().unwrap().utility {
+            Utility::Exact(exact) => Some(exact),
+            _ => None,
         }
     }
 
     pub fn best_action(&self, state: &mut G) -> Option<G::Action> {
+        if let Some(action) = self.oracle_action(state) {
+            return Some(action);
+        }
+        if let Some(action) = self.gumbel_best_action(state) {
+            return Some(action);
+        }
         let current_player = state.current_player();
 
         let mut best_action = None;
@@ -49,7 +520,9 @@ impl<G: Game> MonteCarloTree<G> {
                 match child.utility {
                     // If the node has an approximate value, compare it to the previously set
                     // best potential value.
-                    Utility::Approximate(exploitation) => {
+                    Utility::Approximate(_) => {
+                        let exploitation =
+                            self.exploitation(&child, current_player == state.current_player());
                         if best_exploitation < Some(exploitation) {
                             best_exploitation = Some(exploitation);
                             best_action = Some(action)
@@ -71,9 +544,12 @@ impl<G: Game> MonteCarloTree<G> {
                     //   in which case this is the best option yet.
                     // - a score is set (i.e. a node with an approximate value was considered)
                     //   in this case, we only choose this as our best option if the approximation
-                    //   hold a really low exploration/exploitation ratio.
+                    //   is worse than a draw.
+                    // Either way, the draw's value is then used to compare it to the
+                    // following nodes.
                     Utility::Exact(ExactUtility::Draw) => {
-                        if best_exploitation.is_none() {
+                        if best_exploitation < Some(0f32) {
+                            best_exploitation = Some(0f32);
                             best_action = Some(action)
                         }
                     }
@@ -91,165 +567,627 @@ impl<G: Game> MonteCarloTree<G> {
             state.undo()
         }
 
+        // Nodes proven by a minimax search on expansion have no child telling the
+        // winning action, which is then searched again.
+        let won = self.nodes.get(&state.hash()).is_some_and(|node| {
+            matches!(
+                node.lock().unwrap().utility,
+                Utility::Exact(ExactUtility::Win(p)) if p == current_player
+            )
+        });
+        if won {
+            if let Some(action) = self
+                .config
+                .expansion_minimax
+                .and_then(|depth| minimax::winning_action(state, depth))
+            {
+                return Some(action);
+            }
+        }
+
         best_action
     }
 
     /// Expands the tree by proceeding to a selection/expansion/simulation/backpropagation
     /// routine.
     pub fn step(&mut self, state: &mut G) {
-        // Keeps track of visited nodes for backpropagation.
+        let start = Instant::now();
+        self.stats.iterations += 1;
+        self.iterate(state);
+        self.stats.elapsed += start.elapsed();
+    }
+
+    fn iterate(&mut self, state: &mut G) {
+        self.observer.iteration_started(state);
+        // Keeps track of visited nodes for backpropagation, as well as the actions
+        // played from each of them along with the player that played them.
         let mut visited = vec![];
+        let mut path = vec![];
+        self.playout_amaf.clear();
+        // Positions of the current path, used to detect cycles.
+        let mut positions = HashSet::new();
+        let mut cycle = false;
 
         // Selection phase
         // This phase traverses the tree, searching for any unexpanded node.
         // At the end of this loop, `state` is a game state which hasn't been expanded yet.
-        'selection: while let Some(node) = self.nodes.get(&state.hash()) {
+        'selection: while let Some(node) = self.nodes.get(&state.hash()).cloned() {
+            // If we went back to a position of the current path, the game loops: we stop
+            // there and score this simulation as a draw.
+            if !positions.insert(state.hash()) {
+                cycle = true;
+                break 'selection;
+            }
             let current_player = state.current_player();
+            let (parent_visits, actions, pruned, mut priors, amaf) = {
+                let mut node = node.lock().unwrap();
+                let node = &mut *node;
+                // The root itself might be solved already, in which case there
+                // is nothing left to search.
+                if visited.is_empty() && matches!(node.utility, Utility::Exact(_)) {
+                    return;
+                }
+                let priors = match (self.config.selection, self.config.root_search) {
+                    (Selection::Uct { .. }, RootSearch::Selection)
+                        if self.config.progressive_bias.is_none() =>
+                    {
+                        None
+                    }
+                    _ => node.priors.clone(),
+                };
+
+                // With progressive widening, only the most promising actions are
+                // considered: those with the highest priors if there are any, or
+                // the first ones given by the game otherwise.
+                let actions = state.actions().into_iter();
+                let limit = self
+                    .widening_limit(node.visits)
+                    .map(|limit| limit + node.extra_children);
+                let (actions, pruned, priors) = match (limit, &node.priors) {
+                    (None, _) => (actions.collect::<Vec<_>>(), false, priors),
+                    (Some(limit), Some(node_priors)) => {
+                        let mut actions = actions.map(Some).collect::<Vec<_>>();
+                        let ranking = node
+                            .ranking
+                            .get_or_insert_with(|| rank_by_prior(node_priors, actions.len()));
+                        let pruned = ranking.len() > limit;
+                        let ranked = &ranking[..limit.min(ranking.len())];
+                        let priors = priors.map(|priors| {
+                            ranked
+                                .iter()
+                                .map(|i| priors.get(*i).copied().unwrap_or(0f32))
+                                .collect()
+                        });
+                        let actions = ranked.iter().map(|i| actions[*i].take().unwrap());
+                        (actions.collect(), pruned, priors)
+                    }
+                    (Some(limit), None) => {
+                        let mut actions = actions.take(limit + 1).collect::<Vec<_>>();
+                        let pruned = actions.len() > limit;
+                        actions.truncate(limit);
+                        (actions, pruned, priors)
+                    }
+                };
+
+                let amaf = self.config.rave.map(|_| {
+                    actions
+                        .iter()
+                        .map(|action| {
+                            let key = state.action_key(action)?;
+                            node.amaf.get(&key).copied()
+                        })
+                        .collect::<Vec<_>>()
+                });
+                (node.visits, actions, pruned, priors, amaf)
+            };
+            let is_root = visited.is_empty();
             visited.push(node.clone());
-            let parent_visits = node.lock().unwrap().visits;
+
+            let uniform_prior = 1f32 / actions.len() as f32;
+
+            // Mix noise into the priors of the root node.
+            if let (true, Selection::Puct { .. }, Some(noise)) =
+                (is_root, self.config.selection, self.config.root_noise)
+            {
+                let epsilon = noise.epsilon;
+                let noise = self.root_noise(state, noise.alpha, actions.len());
+                priors = Some(
+                    noise
+                        .iter()
+                        .enumerate()
+                        .map(|(i, n)| {
+                            let prior = priors
+                                .as_ref()
+                                .and_then(|priors| priors.get(i).copied())
+                                .unwrap_or(uniform_prior);
+                            (1f32 - epsilon) * prior + epsilon * n
+                        })
+                        .collect(),
+                );
+            }
+
+            // With some probability, pick a root action uniformly at random instead,
+            // as long as it does not lead to an already solved node.
+            if is_root
+                && !actions.is_empty()
+                && self
+                    .config
+                    .root_epsilon
+                    .is_some_and(|epsilon| self.rng.gen::<f32>() < epsilon)
+            {
+                let action = actions[self.rng.gen_range(0..actions.len())].clone();
+                state.play(&action);
+                let solved = self.nodes.get(&state.hash()).is_some_and(|child| {
+                    matches!(child.lock().unwrap().utility, Utility::Exact(_))
+                });
+                if !solved {
+                    path.push((current_player, action));
+                    continue 'selection;
+                }
+                state.undo();
+            }
+            // With Gumbel search, the root action follows the Sequential Halving
+            // schedule instead of the selection formula.
+            if is_root {
+                if let Some(i) = self.gumbel_root_action(state, &actions, priors.as_deref()) {
+                    state.play(&actions[i]);
+                    let solved = self.nodes.get(&state.hash()).is_some_and(|child| {
+                        matches!(child.lock().unwrap().utility, Utility::Exact(_))
+                    });
+                    if !solved {
+                        path.push((current_player, actions[i].clone()));
+                        continue 'selection;
+                    }
+                    state.undo();
+                }
+            }
+            let first_play_urgency = if is_root {
+                self.config
+                    .root_first_play_urgency
+                    .or(self.config.first_play_urgency)
+            } else {
+                self.config.first_play_urgency
+            };
 
             // Search for the best action to make if any.
             let mut best_action = None;
             let mut best_potential_value: Option<f32> = None;
             let mut best_exact = None;
-            for action in state.actions() {
+            // Unvisited child with the best AMAF value, expanded first with RAVE.
+            let mut best_unvisited: Option<(f32, G::Action)> = None;
+            for (i, action) in actions.into_iter().enumerate() {
                 // Play the action
                 state.play(&action);
+                let prior = priors
+                    .as_ref()
+                    .and_then(|priors| priors.get(i).copied())
+                    .unwrap_or(uniform_prior);
+
+                let amaf = amaf.as_ref().and_then(|amaf| amaf[i]);
 
                 // If the child is expanded already, check its potential
-                if let Some(child) = self.nodes.get(&state.hash()) {
-                    let child = child.lock().unwrap();
-                    match child.utility {
-                        // Compute the exploration/exploitation factor
-                        Utility::Approximate(exploitation) => {
-                            // Exploration is given by the UCT formula.
-                            let exploration =
-                                2f32.sqrt() * ((parent_visits as f32).ln() / (child.visits as f32));
-                            // Exploitation is given for side to move of the child node (aka opponent),
-                            // so we reverse it here.
-                            let potential_value =
-                                exploration - ((exploitation as f32) / (u16::MAX as f32));
-
-                            if best_potential_value < Some(potential_value) {
-                                best_potential_value = Some(potential_value);
-                                best_action = Some(action)
-                            }
+                let child = self.nodes.get(&state.hash()).map(|c| c.lock().unwrap());
+                match child.as_ref().map(|child| child.utility) {
+                    // Compute the exploration/exploitation factor
+                    Some(Utility::Approximate(_)) => {
+                        let child = child.as_ref().unwrap();
+                        // Exploitation is given for side to move of the child node, so we
+                        // reverse it here if it is not the current player.
+                        let exploitation =
+                            self.exploitation(child, current_player == state.current_player());
+                        let exploitation = self.rave_exploitation(exploitation, child.visits, amaf);
+                        let potential_value =
+                            self.potential_value(exploitation, child.visits, parent_visits, prior);
+
+                        if best_potential_value < Some(potential_value) {
+                            best_potential_value = Some(potential_value);
+                            best_action = Some(action)
+                        }
+                    }
+                    // If a child has an exact value, we do not need to search it further.
+                    // However, we still need to check if we can win in any way, as if all
+                    // of the children are assigned exact values, we can propagate it
+                    // to this node.
+                    Some(Utility::Exact(exact_utility)) => {
+                        if best_exact
+                            .map(|best| match (best, exact_utility) {
+                                // We always want to favor winning
+                                (_, ExactUtility::Win(p)) if p == current_player => true,
+                                // If we have the choice between a win for the other player
+                                // and a draw, favor the draw
+                                (ExactUtility::Win(p), ExactUtility::Draw)
+                                    if p != current_player =>
+                                {
+                                    true
+                                }
+                                // Otherwise, consider that the current best is better
+                                (_, _) => false,
+                            })
+                            .unwrap_or(true)
+                        {
+                            best_exact = Some(exact_utility)
+                        }
+                    }
+                    // If a child has not been expanded yet, it is given the first play
+                    // urgency value if any, or the AMAF value of its action with RAVE.
+                    _ if first_play_urgency.is_some() => {
+                        let value = self.amaf_value(amaf).or(first_play_urgency).unwrap();
+                        let potential_value = self.potential_value(value, 0, parent_visits, prior);
+
+                        if best_potential_value < Some(potential_value) {
+                            best_potential_value = Some(potential_value);
+                            best_action = Some(action)
                         }
-                        // If a child has an exact value, we do not need to search it further.
-                        // However, we still need to check if we can win in any way, as if all
-                        // of the children are assigned exact values, we can propagate it
-                        // to this node.
-                        Utility::Exact(exact_utility) => {
-                            if best_exact
-                                .map(|best| match (best, exact_utility) {
-                                    // We always want to favor winning
-                                    (_, ExactUtility::Win(p)) if p == current_player => true,
-                                    // If we have the choice between a win for the other player
-                                    // and a draw, favor the draw
-                                    (ExactUtility::Win(p), ExactUtility::Draw)
-                                        if p != current_player =>
-                                    {
-                                        true
-                                    }
-                                    // Otherwise, consider that the current best is better
-                                    (_, _) => false,
-                                })
-                                .unwrap_or(true)
+                    }
+                    // Otherwise, we always expand it, unvisited children with AMAF
+                    // statistics being expanded in order of their AMAF value.
+                    _ => match self.amaf_value(amaf) {
+                        Some(value) => {
+                            if best_unvisited
+                                .as_ref()
+                                .is_none_or(|(best, _)| value > *best)
                             {
-                                best_exact = Some(exact_utility)
+                                best_unvisited = Some((value, action));
                             }
                         }
-                        // If a child has not been expanded yet, we always expand it
-                        Utility::Unknown => {
+                        None => {
+                            path.push((current_player, action));
                             break 'selection;
                         }
-                    }
-                } else {
-                    break 'selection;
+                    },
                 }
-
                 state.undo();
             }
 
+            // A node with a winning child is won, whatever its other children are.
+            let won = matches!(best_exact, Some(ExactUtility::Win(p)) if p == current_player);
+
+            if let Some((_, action)) = best_unvisited.filter(|_| !won) {
+                state.play(&action);
+                path.push((current_player, action));
+                break 'selection;
+            }
+
             // If the node has a best action, play it then so that we're in an unexpanded
             // state.
-            if let Some(best_action) = best_action {
+            if let Some(best_action) = best_action.filter(|_| !won) {
                 state.play(&best_action);
+                path.push((current_player, best_action));
             }
-            // Otherwise, all of its children are [Exact] nodes. In this case,
-            // we can propagate this result to the parent node and choose another
+            // Otherwise, all of the considered children are [Exact] nodes. If progressive
+            // widening left some children out, and none of the considered ones is
+            // a win, we widen the node further and search it again.
+            else if pruned && !won {
+                node.lock().unwrap().extra_children += 1;
+                visited.pop();
+                positions.remove(&state.hash());
+                continue 'selection;
+            }
+            // Otherwise, the node is won or all of its children are [Exact] nodes. In
+            // this case, we can propagate this result to the parent node and choose another
             // path as this node is completely explored.
             else if let Some(best_exact) = best_exact {
                 node.lock().unwrap().utility = Utility::Exact(best_exact);
+                self.observer.node_proven(state, best_exact);
+                self.stats.proven_nodes += 1;
                 visited.pop();
                 // We visited the entire tree and have found an exact value
                 if visited.is_empty() {
                     return;
                 }
+                positions.remove(&state.hash());
                 state.undo();
+                positions.remove(&state.hash());
                 visited.pop();
+                path.pop();
             } else {
                 unreachable!("Visited a node with no successors")
             }
         }
 
+        let depth = path.len() as u32;
+        self.stats.max_depth = self.stats.max_depth.max(depth);
+        self.stats.total_depth += depth as u64;
+
         // Expansion phase
         // The current state is unexplored, we expand it and assign it a utility value.
-        let utility = match state.utility() {
-            // If the utility of this node is not known, we make random playouts to
-            // assign it an approximate value.
-            Utility::Unknown => self.simulate(state, self.simulations_per_node),
-            u => u,
+        let (utility, evaluation) = match state.utility() {
+            // Nodes closing a cycle are already expanded.
+            _ if cycle => (Utility::Exact(ExactUtility::Draw), None),
+            // If the utility of this node is not known, we look for its exact value
+            // first, then use playouts and/or the evaluator to assign it an
+            // approximate value.
+            Utility::Unknown => match self.prove_leaf(state) {
+                Some(proven) => {
+                    self.observer.node_proven(state, proven);
+                    self.stats.proven_nodes += 1;
+                    (Utility::Exact(proven), None)
+                }
+                None => self.evaluate_leaf(state),
+            },
+            u => (u, None),
         };
-        self.nodes.insert(
-            state.hash(),
-            Arc::new(Mutex::new(MonteCarloNode { utility, visits: 1 })),
-        );
+        let leaf_player = state.current_player();
+        let leaf_value = match utility {
+            Utility::Exact(ExactUtility::Win(p)) => {
+                if p == leaf_player {
+                    1f32
+                } else {
+                    -1f32
+                }
+            }
+            Utility::Exact(ExactUtility::Draw) => 0f32,
+            Utility::Approximate(approx) => dequantize(approx),
+            Utility::Unknown => unreachable!("the returned utility should never be unknown"),
+        };
+        let (minimax, priors) = match evaluation {
+            Some(evaluation) => (evaluation.value, evaluation.priors),
+            None => (quantize(leaf_value), None),
+        };
+        if !cycle {
+            self.nodes.insert(
+                state.hash(),
+                Arc::new(Mutex::new(MonteCarloNode {
+                    utility,
+                    visits: 1,
+                    squares: leaf_value * leaf_value,
+                    minimax,
+                    priors,
+                    amaf: HashMap::new(),
+                    extra_children: 0,
+                    ranking: None,
+                })),
+            );
+            self.observer.node_expanded(state, utility);
+            self.stats.nodes_created += 1;
+        }
 
         // Backpropagation phase
         // We now transmit the change to the nodes we traversed.
         while let Some(node) = visited.pop() {
             state.undo();
 
+            // The leaf value is given for its side to move, reverse it if needed.
+            let value = if state.current_player() == leaf_player {
+                leaf_value
+            } else {
+                -leaf_value
+            };
+            let minimax = if self.config.implicit_minimax.is_some() {
+                self.minimax(state)
+            } else {
+                None
+            };
+
             let mut node = node.lock().unwrap();
+            if self.config.rave.is_some() {
+                let moves = &path[visited.len()..];
+                self.update_amaf(&mut node, state, moves, leaf_player, leaf_value);
+            }
             node.visits += 1;
+            let visits = node.visits;
+            if let Utility::Approximate(approx) = &mut node.utility {
+                let mean = dequantize(*approx);
+                *approx = quantize(mean + (value - mean) / visits as f32);
+            }
+            node.squares += (value * value - node.squares) / visits as f32;
+            if let Some(minimax) = minimax {
+                node.minimax = minimax
+            }
+            drop(node);
+            self.observer.backpropagated(state, value, visits);
+        }
+    }
 
-            match &mut node.utility {
-                Utility::Approximate(approx) => {
-                    let new = match utility {
-                        Utility::Exact(ExactUtility::Win(p)) => {
-                            if p == state.current_player() {
-                                1f32
-                            } else {
-                                -1f32
-                            }
-                        }
-                        Utility::Exact(ExactUtility::Draw) => 0f32,
-                        Utility::Approximate(new) => new as f32 / i16::MAX as f32,
-                        _ => unreachable!("the returned utility should never be unknown"),
-                    };
+    /// Returns the Dirichlet noise of the given root state, sampling it if the root
+    /// changed since the last call.
+    fn root_noise(&mut self, state: &G, alpha: f32, actions: usize) -> &[f32] {
+        let hash = state.hash();
+        match &self.root_noise {
+            Some((root, noise)) if *root == hash && noise.len() == actions => {}
+            _ => {
+                let noise = dirichlet(&mut self.rng, alpha, actions);
+                self.root_noise = Some((hash, noise));
+            }
+        }
+        &self.root_noise.as_ref().unwrap().1
+    }
+
+    /// Looks for the exact utility of an unknown leaf node, probing the oracle and
+    /// searching for a forced win, see [SearchConfig::expansion_minimax].
+    fn prove_leaf(&self, state: &mut G) -> Option<ExactUtility<G>> {
+        if let Some((utility, _)) = self.oracle.as_ref().and_then(|oracle| oracle.probe(state)) {
+            return Some(utility);
+        }
+        let winner = minimax::forced_winner(state, self.config.expansion_minimax?)?;
+        Some(ExactUtility::Win(winner))
+    }
+
+    /// Assigns an approximate value to an unknown leaf node, according to the
+    /// configured [LeafEvaluation]. Also returns the evaluator's result, if it was
+    /// used.
+    fn evaluate_leaf(&mut self, state: &mut G) -> (Utility<G>, Option<Evaluation>) {
+        let uses_evaluations = self.uses_evaluations();
+        let Some(evaluator) = self.evaluator.as_mut() else {
+            return (self.simulate(state, self.config.simulations_per_node), None);
+        };
+
+        match self.config.leaf_evaluation {
+            LeafEvaluation::Playouts => {
+                let evaluation = uses_evaluations.then(|| evaluator.evaluate(state));
+                let utility = self.simulate(state, self.config.simulations_per_node);
+                (utility, evaluation)
+            }
+            LeafEvaluation::Evaluator => {
+                let evaluation = evaluator.evaluate(state);
+                (Utility::Approximate(evaluation.value), Some(evaluation))
+            }
+            LeafEvaluation::Mixed { playout_weight } => {
+                let evaluation = evaluator.evaluate(state);
+                let Utility::Approximate(playouts) =
+                    self.simulate(state, self.config.simulations_per_node)
+                else {
+                    unreachable!("simulations always yield an approximate utility")
+                };
+                let mixed = playout_weight * dequantize(playouts)
+                    + (1f32 - playout_weight) * dequantize(evaluation.value);
+                (Utility::Approximate(quantize(mixed)), Some(evaluation))
+            }
+        }
+    }
 
-                    *approx = ((((*approx as f32 / i16::MAX as f32) + new) / 2f32)
-                        * (i16::MAX as f32)) as i16;
+    /// Whether evaluations are used besides the values of leaves: for their priors
+    /// or for implicit minimax backups.
+    fn uses_evaluations(&self) -> bool {
+        matches!(self.config.selection, Selection::Puct { .. })
+            || matches!(self.config.root_search, RootSearch::Gumbel { .. })
+            || self.config.progressive_bias.is_some()
+            || self.config.widening.is_some()
+            || self.config.implicit_minimax.is_some()
+    }
+
+    /// Returns the number of children of a node with the given number of visits
+    /// that should be considered, if progressive widening is enabled.
+    fn widening_limit(&self, visits: u32) -> Option<usize> {
+        self.config.widening.map(|widening| {
+            (widening.coefficient * (visits as f32).powf(widening.exponent))
+                .ceil()
+                .max(1f32) as usize
+        })
+    }
+
+    /// Combines the exploitation value of a child with the exploration term given by
+    /// the selection formula and the progressive bias. Unvisited children have no
+    /// exploration term with [Selection::Uct].
+    fn potential_value(
+        &self,
+        exploitation: f32,
+        visits: u32,
+        parent_visits: u32,
+        prior: f32,
+    ) -> f32 {
+        let exploration = match self.config.selection {
+            Selection::Uct { .. } if visits == 0 => 0f32,
+            Selection::Uct { exploration } => {
+                exploration * ((parent_visits as f32).ln() / (visits as f32)).sqrt()
+            }
+            Selection::Puct { exploration } => {
+                exploration * prior * (parent_visits as f32).sqrt() / (1f32 + visits as f32)
+            }
+        };
+        let bias = self
+            .config
+            .progressive_bias
+            .map_or(0f32, |weight| weight * prior / (1f32 + visits as f32));
+        exploitation + exploration + bias
+    }
+
+    /// Value of an expanded child node from the point of view of the parent's side
+    /// to move, taking implicit minimax backups into account.
+    fn exploitation(&self, child: &MonteCarloNode<G>, same_player: bool) -> f32 {
+        let mean = match child.utility {
+            Utility::Approximate(approx) => dequantize(approx),
+            _ => dequantize(child.minimax),
+        };
+        let value = match self.config.implicit_minimax {
+            Some(weight) => (1f32 - weight) * mean + weight * dequantize(child.minimax),
+            None => mean,
+        };
+
+        if same_player {
+            value
+        } else {
+            -value
+        }
+    }
+
+    /// Computes the minimax value of a node from its expanded children, if any.
+    fn minimax(&self, state: &mut G) -> Option<i16> {
+        let current_player = state.current_player();
+
+        let mut best = None;
+        for action in state.actions() {
+            state.play(&action);
+            if let Some(child) = self.nodes.get(&state.hash()) {
+                let child = child.lock().unwrap();
+                let value = match child.utility {
+                    Utility::Exact(ExactUtility::Win(p)) if p == state.current_player() => i16::MAX,
+                    Utility::Exact(ExactUtility::Win(_)) => -i16::MAX,
+                    Utility::Exact(ExactUtility::Draw) => 0,
+                    _ => child.minimax,
+                };
+                let value = if state.current_player() == current_player {
+                    value
+                } else {
+                    -value
+                };
+                best = best.max(Some(value));
+            }
+            state.undo();
+        }
+
+        best
+    }
+
+    /// Value given to a playout that was cut off, for the given player.
+    fn cutoff_value(&mut self, state: &G, player: G::Player) -> f32 {
+        match (self.config.playout_cutoff, self.evaluator.as_mut()) {
+            (PlayoutCutoff::Evaluator, Some(evaluator)) => {
+                let value = dequantize(evaluator.evaluate(state).value);
+                if state.current_player() == player {
+                    value
+                } else {
+                    -value
                 }
-                _ => {}
             }
+            _ => 0f32,
         }
     }
 
     /// Simulates a number of games
-    fn simulate(&self, state: &mut G, playouts: u32) -> Utility<G> {
-        let mut rng = rand::thread_rng();
+    ///
+    /// When RAVE is enabled, the actions played during playouts are recorded in
+    /// `playout_amaf`: for each action (and player), the share of playouts in which
+    /// it was played and the sum of their results divided by the number of playouts.
+    fn simulate(&mut self, state: &mut G, playouts: u32) -> Utility<G> {
         let mut approximate_result = 0f32;
         let node_player = state.current_player();
+        let record_moves =
+            self.config.rave.is_some() || self.config.playout_policy != PlayoutPolicy::Uniform;
+        let mut moves = vec![];
+        let mut positions = HashSet::new();
         for _ in 0..playouts {
             // Traverse the game tree randomly until we find a terminal or approximate node.
             let mut plys = 0;
+            if self.config.playout_cycle_detection {
+                positions.clear();
+                positions.insert(state.hash());
+            }
             let result = 'simulation: loop {
-                // Pick random action
-                let action = state.actions().into_iter().choose(&mut rng).unwrap();
+                // Cut the playout off if it gets too long.
+                if self
+                    .config
+                    .max_playout_length
+                    .is_some_and(|length| plys >= length)
+                {
+                    break 'simulation self.cutoff_value(state, node_player);
+                }
+
+                // Pick an action following the playout policy, unless there is a
+                // winning one
+                let action = match self
+                    .config
+                    .playout_minimax
+                    .and_then(|depth| minimax::winning_action(state, depth))
+                {
+                    Some(action) => action,
+                    None => self.playout_action(state, &moves).unwrap(),
+                };
 
                 // Play it
+                if let Some(key) = state.action_key(&action).filter(|_| record_moves) {
+                    moves.push((state.current_player(), key));
+                }
                 state.play(&action);
                 plys += 1;
 
@@ -259,10 +1197,20 @@ impl<G: Game> MonteCarloTree<G> {
                     }
                     Utility::Exact(ExactUtility::Draw) => break 'simulation 0f32,
                     Utility::Approximate(approx) => {
-                        break 'simulation (approx as f32) / (i16::MAX as f32)
+                        let approx = dequantize(approx);
+                        break 'simulation if state.current_player() == node_player {
+                            approx
+                        } else {
+                            -approx
+                        };
                     }
                     Utility::Unknown => {}
                 }
+
+                // Also cut it off if it went back to a position it already went through.
+                if self.config.playout_cycle_detection && !positions.insert(state.hash()) {
+                    break 'simulation self.cutoff_value(state, node_player);
+                }
             };
 
             // Return to the initial state.
@@ -272,18 +1220,584 @@ impl<G: Game> MonteCarloTree<G> {
 
             // Then change the approximate value
             approximate_result += result;
+            self.observer.playout_finished(state, plys, result);
+            self.stats.playouts += 1;
+            self.stats.playout_plies += plys as u64;
+
+            self.update_playout_statistics(&moves, node_player, result);
+
+            // Record the first occurrence of each action for AMAF statistics.
+            let mut seen = HashSet::new();
+            for m in moves.drain(..) {
+                if seen.insert(m) {
+                    let (samples, sum) = self.playout_amaf.entry(m).or_insert((0f32, 0f32));
+                    *samples += 1f32;
+                    *sum += result;
+                }
+            }
+        }
+        for (samples, sum) in self.playout_amaf.values_mut() {
+            *samples /= playouts as f32;
+            *sum /= playouts as f32;
         }
 
         // We now compute the approximate value aka the approximate value
         // divided by the number of simulations.
-        Utility::Approximate(
-            (approximate_result / (self.simulations_per_node as f32) * (i16::MAX as f32)) as i16,
-        )
+        Utility::Approximate(quantize(approximate_result / (playouts as f32)))
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// Derives the seed of a random stream from a base seed, using the SplitMix64
+/// finalizer so that consecutive streams get unrelated seeds.
+fn derive_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed ^ stream.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Samples a symmetric Dirichlet distribution of the given dimension.
+fn dirichlet(rng: &mut impl Rng, alpha: f32, dimension: usize) -> Vec<f32> {
+    let gamma = Gamma::new(alpha, 1f32).expect("the Dirichlet concentration should be positive");
+    let mut sample = (0..dimension)
+        .map(|_| gamma.sample(rng))
+        .collect::<Vec<f32>>();
+    let sum = sample.iter().sum::<f32>();
+    if sum > 0f32 {
+        sample.iter_mut().for_each(|x| *x /= sum);
+    }
+    sample
+}
+
+/// Returns the indices of a node's actions sorted by decreasing prior, actions
+/// without a prior counting as 0.
+fn rank_by_prior(priors: &[f32], actions: usize) -> Vec<usize> {
+    let prior = |i: usize| priors.get(i).copied().unwrap_or(0f32);
+    let mut ranking = (0..actions).collect::<Vec<_>>();
+    ranking.sort_by(|i, j| prior(*j).total_cmp(&prior(*i)));
+    ranking
+}
+
+/// Converts a value between -1 and 1 to the quantized representation used by
+/// [Utility::Approximate].
+fn quantize(value: f32) -> i16 {
+    (value.clamp(-1f32, 1f32) * (i16::MAX as f32)) as i16
+}
+
+/// Converts a quantized value back to a value between -1 and 1.
+fn dequantize(value: i16) -> f32 {
+    (value as f32 / i16::MAX as f32).max(-1f32)
+}
+
+#[derive(Clone, PartialEq)]
 pub struct MonteCarloNode<G: Game> {
     utility: Utility<G>,
     visits: u32,
+    // Mean of the squared results of simulations, for the side to move.
+    squares: f32,
+    // Minimax value of the evaluations in this node's subtree, for the side to move.
+    minimax: i16,
+    // Prior probabilities of the actions, in the order given by [Game::actions].
+    priors: Option<Vec<f32>>,
+    // AMAF statistics of the actions played by the side to move in simulations
+    // going through this node, by action key: number of samples and sum of their
+    // results.
+    amaf: HashMap<u64, (f32, f32)>,
+    // Children considered in addition to the progressive widening limit, because
+    // all of the others were solved.
+    extra_children: usize,
+    // Indices of the actions by decreasing prior, computed once for progressive
+    // widening.
+    ranking: Option<Vec<usize>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Subtraction;
+
+    /// A game of a single move each: the first player either offers a draw or
+    /// passes, after which the second player wins with all but one of its moves.
+    #[derive(Clone, Debug)]
+    struct Offer {
+        history: Vec<u8>,
+    }
+    impl Game for Offer {
+        type Action = u8;
+        type ActionsIter = Vec<u8>;
+        type Hash = Vec<u8>;
+        type Player = u8;
+
+        fn play(&mut self, action: &u8) {
+            self.history.push(*action);
+        }
+        fn undo(&mut self) {
+            self.history.pop();
+        }
+
+        fn current_player(&self) -> u8 {
+            self.history.len() as u8 % 2
+        }
+        fn actions(&self) -> Vec<u8> {
+            match self.history[..] {
+                // Pass, then offer a draw.
+                [] => vec![0, 1],
+                _ => (0..10).collect(),
+            }
+        }
+
+        fn utility(&self) -> Utility<Self> {
+            match self.history[..] {
+                [1] | [0, 0] => Utility::Exact(ExactUtility::Draw),
+                [0, _] => Utility::Exact(ExactUtility::Win(1)),
+                _ => Utility::Unknown,
+            }
+        }
+        fn hash(&self) -> Vec<u8> {
+            self.history.clone()
+        }
+    }
+
+    /// A trap for random playouts: the first player either settles for a position
+    /// where most replies of the second player lose and the others draw, or plays
+    /// a move after which it wins by answering each of the ten replies of the
+    /// second player with the same number, and loses otherwise.
+    #[derive(Clone, Debug)]
+    struct Trap {
+        history: Vec<u8>,
+    }
+    impl Game for Trap {
+        type Action = u8;
+        type ActionsIter = Vec<u8>;
+        type Hash = Vec<u8>;
+        type Player = u8;
+
+        fn play(&mut self, action: &u8) {
+            self.history.push(*action);
+        }
+        fn undo(&mut self) {
+            self.history.pop();
+        }
+
+        fn current_player(&self) -> u8 {
+            self.history.len() as u8 % 2
+        }
+        fn actions(&self) -> Vec<u8> {
+            match self.history[..] {
+                // Settle, then go for the forced win.
+                [] => vec![0, 1],
+                _ => (0..10).collect(),
+            }
+        }
+
+        fn utility(&self) -> Utility<Self> {
+            match self.history[..] {
+                [0, reply] if reply < 7 => Utility::Exact(ExactUtility::Win(0)),
+                [0, _] => Utility::Exact(ExactUtility::Draw),
+                [1, reply, answer] if reply == answer => Utility::Exact(ExactUtility::Win(0)),
+                [1, _, _] => Utility::Exact(ExactUtility::Win(1)),
+                _ => Utility::Unknown,
+            }
+        }
+        fn hash(&self) -> Vec<u8> {
+            self.history.clone()
+        }
+    }
+
+    fn trap_best_action(config: SearchConfig) -> Option<u8> {
+        let mut state = Trap { history: vec![] };
+        let mut tree = MonteCarloTree::with_config(SearchConfig {
+            seed: Some(0),
+            ..config
+        });
+        for _ in 0..30 {
+            tree.step(&mut state);
+        }
+        tree.best_action(&mut state)
+    }
+
+    #[test]
+    fn best_action_prefers_draws_to_worse_values() {
+        let mut state = Offer { history: vec![] };
+        let mut tree = MonteCarloTree::with_config(SearchConfig {
+            seed: Some(0),
+            ..Default::default()
+        });
+        // Expand the root and both of its children, passing being evaluated by
+        // playouts that mostly lose.
+        for _ in 0..3 {
+            tree.step(&mut state);
+        }
+
+        assert_eq!(tree.best_action(&mut state), Some(1));
+    }
+
+    #[test]
+    fn shallow_minimax_finds_forced_wins_missed_by_playouts() {
+        assert_eq!(trap_best_action(SearchConfig::default()), Some(0));
+        let expansion = SearchConfig {
+            expansion_minimax: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(trap_best_action(expansion), Some(1));
+        let playouts = SearchConfig {
+            playout_minimax: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(trap_best_action(playouts), Some(1));
+    }
+
+    #[test]
+    fn uct_exploration_decays_with_the_square_root_of_visits() {
+        let tree = MonteCarloTree::<Subtraction>::new();
+        let exploration = |visits| tree.potential_value(0f32, visits, 100, 0f32);
+
+        let expected = 2f32.sqrt() * (100f32.ln() / 4f32).sqrt();
+        assert!((exploration(4) - expected).abs() < 1e-6);
+        assert!((exploration(1) / exploration(4) - 2f32).abs() < 1e-6);
+    }
+
+    #[test]
+    fn backpropagation_keeps_the_mean_of_results() {
+        let mut state = Subtraction::new(5);
+        let mut tree = MonteCarloTree::with_config(SearchConfig {
+            leaf_evaluation: LeafEvaluation::Evaluator,
+            seed: Some(0),
+            ..Default::default()
+        })
+        .with_evaluator(|state: &Subtraction| Evaluation::value(state.pile as i16 * 1000));
+        // Expand the root, then each of its children once.
+        for _ in 0..4 {
+            tree.step(&mut state);
+        }
+
+        // Children values are for the opponent, so they count against the root.
+        let root = tree.nodes[&state.hash()].lock().unwrap();
+        assert_eq!(root.visits, 4);
+        let Utility::Approximate(value) = root.utility else {
+            panic!("the root should not be solved");
+        };
+        let expected = (5000 - 4000 - 3000 - 2000) / 4;
+        assert!((value - expected).abs() <= 2, "{value} != {expected}");
+    }
+
+    #[test]
+    fn root_noise_follows_the_seed() {
+        let noise = |seed| {
+            let mut state = Subtraction::new(10);
+            let mut tree = MonteCarloTree::with_config(SearchConfig {
+                selection: Selection::Puct { exploration: 1f32 },
+                root_noise: Some(DirichletNoise {
+                    alpha: 0.3,
+                    epsilon: 0.25,
+                }),
+                seed: Some(seed),
+                ..Default::default()
+            });
+            for _ in 0..10 {
+                tree.step(&mut state);
+            }
+            tree.root_noise.unwrap().1
+        };
+
+        assert_eq!(noise(0), noise(0));
+        assert_ne!(noise(0), noise(1));
+    }
+
+    #[test]
+    fn searches_with_the_same_seed_are_identical() {
+        let search = || {
+            let mut state = Subtraction::new(15);
+            let mut tree = MonteCarloTree::with_config(SearchConfig {
+                root_epsilon: Some(0.2),
+                seed: Some(0),
+                ..Default::default()
+            });
+            let mut results = vec![];
+            for _ in 0..3 {
+                for _ in 0..200 {
+                    tree.step(&mut state);
+                }
+                let action = tree.best_action(&mut state).unwrap();
+                results.push((tree.visit_counts(&mut state), action));
+                state.play(&action);
+                tree.retain_subtree(&mut state);
+            }
+            results
+        };
+
+        assert_eq!(search(), search());
+    }
+
+    #[test]
+    fn amaf_statistics_need_action_keys() {
+        let config = SearchConfig {
+            rave: Some(RaveSchedule::Equivalence { k: 100f32 }),
+            seed: Some(0),
+            ..Default::default()
+        };
+        let mut state = Subtraction::new(10);
+        let mut tree = MonteCarloTree::with_config(config.clone());
+        for _ in 0..50 {
+            tree.step(&mut state);
+        }
+        assert!(!tree.nodes[&state.hash()].lock().unwrap().amaf.is_empty());
+
+        // Games without action keys are searched without AMAF statistics.
+        let mut state = Offer { history: vec![] };
+        let mut tree = MonteCarloTree::with_config(config);
+        for _ in 0..50 {
+            tree.step(&mut state);
+        }
+        assert_eq!(tree.best_action(&mut state), Some(1));
+        assert!(tree
+            .nodes
+            .values()
+            .all(|node| node.lock().unwrap().amaf.is_empty()));
+    }
+
+    /// A game whose players pick one of the first `choices` numbers, drawn after
+    /// `length` moves.
+    #[derive(Clone, Debug)]
+    struct Numbers {
+        history: Vec<u32>,
+        choices: usize,
+        length: usize,
+    }
+    impl Game for Numbers {
+        type Action = u32;
+        type ActionsIter = std::iter::Take<std::ops::RangeFrom<u32>>;
+        type Hash = Vec<u32>;
+        type Player = u8;
+
+        fn play(&mut self, action: &u32) {
+            self.history.push(*action);
+        }
+        fn undo(&mut self) {
+            self.history.pop();
+        }
+
+        fn current_player(&self) -> u8 {
+            self.history.len() as u8 % 2
+        }
+        fn actions(&self) -> Self::ActionsIter {
+            (0..).take(self.choices)
+        }
+
+        fn utility(&self) -> Utility<Self> {
+            if self.history.len() >= self.length {
+                Utility::Exact(ExactUtility::Draw)
+            } else {
+                Utility::Unknown
+            }
+        }
+        fn hash(&self) -> Vec<u32> {
+            self.history.clone()
+        }
+    }
+
+    fn expanded_children<G: Game>(
+        tree: &MonteCarloTree<G>,
+        state: &mut G,
+        actions: &[G::Action],
+    ) -> usize {
+        actions
+            .iter()
+            .filter(|action| {
+                state.play(action);
+                let expanded = tree.nodes.contains_key(&state.hash());
+                state.undo();
+                expanded
+            })
+            .count()
+    }
+
+    #[test]
+    fn widening_limit_grows_with_visits() {
+        let tree = MonteCarloTree::<Subtraction>::with_config(SearchConfig {
+            widening: Some(ProgressiveWidening {
+                coefficient: 2f32,
+                exponent: 0.5,
+            }),
+            ..Default::default()
+        });
+        let limits = [1, 4, 9, 10, 100].map(|visits| tree.widening_limit(visits));
+        assert_eq!(limits, [2, 4, 6, 7, 20].map(Some));
+    }
+
+    #[test]
+    fn widening_consumes_actions_lazily() {
+        let mut state = Numbers {
+            history: vec![],
+            choices: usize::MAX,
+            length: 2,
+        };
+        let mut tree = MonteCarloTree::with_config(SearchConfig {
+            leaf_evaluation: LeafEvaluation::Evaluator,
+            widening: Some(ProgressiveWidening {
+                coefficient: 1f32,
+                exponent: 0.5,
+            }),
+            seed: Some(0),
+            ..Default::default()
+        })
+        .with_evaluator(|_: &Numbers| Evaluation::value(0));
+        // The actions are endless, so the search only terminates if it stops
+        // generating them past the widening limit.
+        for _ in 0..30 {
+            tree.step(&mut state);
+        }
+
+        let visits = tree.nodes[&state.hash()].lock().unwrap().visits;
+        let limit = tree.widening_limit(visits).unwrap();
+        let actions = (0..limit as u32 + 1).collect::<Vec<_>>();
+        assert_eq!(expanded_children(&tree, &mut state, &actions), limit);
+    }
+
+    #[test]
+    fn widening_follows_cached_prior_ranking() {
+        let mut state = Subtraction::new(10);
+        let mut tree = MonteCarloTree::with_config(SearchConfig {
+            leaf_evaluation: LeafEvaluation::Evaluator,
+            widening: Some(ProgressiveWidening {
+                coefficient: 1f32,
+                exponent: 0f32,
+            }),
+            seed: Some(0),
+            ..Default::default()
+        })
+        .with_evaluator(|_: &Subtraction| Evaluation {
+            value: 0,
+            priors: Some(vec![0.2, 0.3, 0.5]),
+        });
+        for _ in 0..5 {
+            tree.step(&mut state);
+        }
+
+        // Only the child with the highest prior is considered.
+        assert_eq!(expanded_children(&tree, &mut state, &[1, 2]), 0);
+        assert_eq!(expanded_children(&tree, &mut state, &[3]), 1);
+        let root = tree.nodes[&state.hash()].lock().unwrap();
+        assert_eq!(root.ranking, Some(vec![2, 1, 0]));
+    }
+
+    #[test]
+    fn widening_grows_once_considered_children_are_solved() {
+        // The only winning move takes 2 tokens, which is not the first action.
+        let mut state = Subtraction::new(6);
+        let mut tree = MonteCarloTree::with_config(SearchConfig {
+            widening: Some(ProgressiveWidening {
+                coefficient: 1f32,
+                exponent: 0f32,
+            }),
+            seed: Some(0),
+            ..Default::default()
+        });
+        for _ in 0..200 {
+            tree.step(&mut state);
+        }
+
+        let root = tree.nodes[&state.hash()].lock().unwrap();
+        assert!(matches!(root.utility, Utility::Exact(ExactUtility::Win(0))));
+        assert!(root.extra_children >= 1);
+        drop(root);
+        assert_eq!(tree.best_action(&mut state), Some(2));
+    }
+
+    /// A tree valuing every leaf as even with the given priors, so that selection
+    /// only depends on visits and priors.
+    fn even_tree<G: Game + 'static>(
+        config: SearchConfig,
+        priors: Option<Vec<f32>>,
+    ) -> MonteCarloTree<G> {
+        MonteCarloTree::with_config(SearchConfig {
+            leaf_evaluation: LeafEvaluation::Evaluator,
+            seed: Some(0),
+            ..config
+        })
+        .with_evaluator(move |_: &G| Evaluation {
+            value: 0,
+            priors: priors.clone(),
+        })
+    }
+
+    #[test]
+    fn playouts_only_call_the_evaluator_when_needed() {
+        let calls = |config| {
+            let calls = Arc::new(Mutex::new(0));
+            let counter = calls.clone();
+            let mut tree =
+                MonteCarloTree::with_config(config).with_evaluator(move |_: &Subtraction| {
+                    *counter.lock().unwrap() += 1;
+                    Evaluation::value(0)
+                });
+            let mut state = Subtraction::new(10);
+            for _ in 0..10 {
+                tree.step(&mut state);
+            }
+            let calls = *calls.lock().unwrap();
+            calls
+        };
+
+        assert_eq!(calls(SearchConfig::default()), 0);
+        assert!(
+            calls(SearchConfig {
+                progressive_bias: Some(1f32),
+                ..Default::default()
+            }) > 0
+        );
+    }
+
+    #[test]
+    fn progressive_bias_favors_high_priors() {
+        let revisited = |progressive_bias| {
+            let config = SearchConfig {
+                progressive_bias,
+                ..Default::default()
+            };
+            let mut tree = even_tree::<Subtraction>(config, Some(vec![0f32, 0f32, 1f32]));
+            let mut state = Subtraction::new(10);
+            // Expand the root and its children, then revisit one of them.
+            for _ in 0..5 {
+                tree.step(&mut state);
+            }
+            (1..=3)
+                .find(|&action| {
+                    state.play(&action);
+                    let visits = tree.nodes[&state.hash()].lock().unwrap().visits;
+                    state.undo();
+                    visits == 2
+                })
+                .unwrap()
+        };
+
+        assert_eq!(revisited(None), 1);
+        assert_eq!(revisited(Some(1f32)), 3);
+    }
+
+    #[test]
+    fn first_play_urgency_applies_below_the_root() {
+        let mut tree = even_tree(
+            SearchConfig {
+                first_play_urgency: Some(-1f32),
+                root_first_play_urgency: Some(1f32),
+                ..Default::default()
+            },
+            None,
+        );
+        let mut state = Numbers {
+            history: vec![],
+            choices: 3,
+            length: 100,
+        };
+        for _ in 0..30 {
+            tree.step(&mut state);
+        }
+
+        // Unvisited children look bad below the root, so a single one is expanded,
+        // while they look good at the root.
+        assert!(expanded_children(&tree, &mut state, &[0, 1, 2]) > 1);
+        state.play(&0);
+        assert_eq!(expanded_children(&tree, &mut state, &[0, 1, 2]), 1);
+    }
 }
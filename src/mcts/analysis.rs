@@ -0,0 +1,130 @@
+//! # Search analysis
+//! Reports of what the search thinks of a position: statistics of each action and
+//! the principal variation, i.e. the sequence of moves the search expects.
+
+use rand::RngCore;
+use std::cmp::Reverse;
+
+use super::{dequantize, MonteCarloTree, SearchObserver};
+use crate::game::{ExactUtility, Game, Utility};
+
+// Quantile of the normal distribution used for confidence intervals (95%).
+const CONFIDENCE_QUANTILE: f32 = 1.96;
+
+/// Analysis of a position, see [MonteCarloTree::analysis].
+pub struct Analysis<G: Game> {
+    /// Number of visits of the analysed position.
+    pub visits: u32,
+    /// Statistics of each action, from the most visited to the least visited.
+    pub actions: Vec<ActionAnalysis<G>>,
+    /// The sequence of moves expected by the search, starting with the best action.
+    pub principal_variation: Vec<G::Action>,
+}
+
+/// Statistics of an action, from the point of view of the player making it.
+pub struct ActionAnalysis<G: Game> {
+    pub action: G::Action,
+    /// Number of visits of the position reached by the action.
+    pub visits: u32,
+    /// Mean result of the simulations going through the action, between -1 and 1.
+    /// [None] if the action was never searched.
+    pub value: Option<f32>,
+    /// 95% confidence interval of the value.
+    pub confidence_interval: Option<(f32, f32)>,
+    /// Exact value of the action, if it was proven.
+    pub proven: Option<ExactUtility<G>>,
+    /// The sequence of moves expected after the action, starting with it.
+    pub variation: Vec<G::Action>,
+}
+
+impl<G: Game, O: SearchObserver<G>, R: RngCore> MonteCarloTree<G, O, R> {
+    /// Reports the statistics of each action from the given state, along with the
+    /// principal variation up to [SearchConfig::pv_depth](super::SearchConfig::pv_depth)
+    /// moves.
+    pub fn analysis(&self, state: &mut G) -> Analysis<G> {
+        let current_player = state.current_player();
+        let visits = self
+            .nodes
+            .get(&state.hash())
+            .map_or(0, |node| node.lock().unwrap().visits);
+
+        let mut actions = state
+            .actions()
+            .into_iter()
+            .map(|action| {
+                state.play(&action);
+                let same_player = state.current_player() == current_player;
+                let sign = if same_player { 1f32 } else { -1f32 };
+                let mut analysis = ActionAnalysis {
+                    visits: 0,
+                    value: None,
+                    confidence_interval: None,
+                    proven: None,
+                    variation: vec![],
+                    action,
+                };
+                if let Some(child) = self.nodes.get(&state.hash()) {
+                    let child = child.lock().unwrap();
+                    analysis.visits = child.visits;
+                    match child.utility {
+                        Utility::Exact(exact) => {
+                            let value = match exact {
+                                ExactUtility::Win(p) if p == current_player => 1f32,
+                                ExactUtility::Win(_) => -1f32,
+                                ExactUtility::Draw => 0f32,
+                            };
+                            analysis.value = Some(value);
+                            analysis.confidence_interval = Some((value, value));
+                            analysis.proven = Some(exact);
+                        }
+                        Utility::Approximate(approx) => {
+                            let mean = dequantize(approx);
+                            let variance = (child.squares - mean * mean).max(0f32);
+                            let margin =
+                                CONFIDENCE_QUANTILE * (variance / child.visits as f32).sqrt();
+                            let value = sign * mean;
+                            analysis.value = Some(value);
+                            analysis.confidence_interval =
+                                Some(((value - margin).max(-1f32), (value + margin).min(1f32)));
+                        }
+                        Utility::Unknown => {}
+                    }
+                    drop(child);
+                    analysis.variation = self.principal_variation(state, self.config.pv_depth);
+                }
+                state.undo();
+                analysis.variation.insert(0, analysis.action.clone());
+                analysis
+            })
+            .collect::<Vec<_>>();
+        actions.sort_by_key(|analysis| Reverse(analysis.visits));
+
+        Analysis {
+            visits,
+            actions,
+            principal_variation: self.principal_variation(state, self.config.pv_depth + 1),
+        }
+    }
+
+    /// Follows the best actions from the given state, as long as they lead to
+    /// expanded nodes, up to the given depth.
+    fn principal_variation(&self, state: &mut G, depth: usize) -> Vec<G::Action> {
+        let mut variation = vec![];
+        while variation.len() < depth {
+            let Some(action) = self.best_action(state) else {
+                break;
+            };
+            state.play(&action);
+            if !self.nodes.contains_key(&state.hash()) {
+                state.undo();
+                break;
+            }
+            variation.push(action);
+        }
+
+        for _ in 0..variation.len() {
+            state.undo();
+        }
+        variation
+    }
+}
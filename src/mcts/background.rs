@@ -0,0 +1,262 @@
+//! # Background search
+//! Searching on a separate thread, so that interactive applications can keep
+//! responding while the tree grows, and stop the search whenever they need a move.
+//!
+//! The search thread owns the tree and the root state, and runs
+//! [MonteCarloTree::step] until it is stopped, idling while the root is solved. The tree is
+//! shared with the [SearchHandle] through a mutex released after every step, so that
+//! the best action found so far can be queried at any moment.
+//!
+//! ## Pondering
+//! The search does not need to stop between moves: once a move is chosen, playing
+//! it with [SearchHandle::play] makes the search continue from the resulting
+//! position while the opponent thinks. When the opponent's move arrives, playing it
+//! as well keeps the statistics gathered for that position, which then serve the
+//! search of the next move.
+
+use rand::{rngs::StdRng, RngCore};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use super::{MonteCarloTree, SearchObserver};
+use crate::game::{Game, Utility};
+
+// State shared between a handle and its search thread.
+struct Shared<G: Game, O, R> {
+    search: Mutex<(MonteCarloTree<G, O, R>, G)>,
+    stop: AtomicBool,
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+/// Handle to a search running on a background thread, see [MonteCarloTree::spawn].
+///
+/// Dropping the handle stops the search and waits for the thread to finish.
+pub struct SearchHandle<G: Game, O = (), R = StdRng> {
+    shared: Arc<Shared<G, O, R>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<G, O, R> MonteCarloTree<G, O, R>
+where
+    G: Game + Send + 'static,
+    O: SearchObserver<G> + 'static,
+    R: RngCore + 'static,
+    MonteCarloTree<G, O, R>: Send,
+{
+    /// Moves the tree to a background thread searching from the given state, until
+    /// it is stopped through the returned handle.
+    pub fn spawn(self, state: G) -> SearchHandle<G, O, R> {
+        let shared = Arc::new(Shared {
+            search: Mutex::new((self, state)),
+            stop: AtomicBool::new(false),
+            paused: Mutex::new(false),
+            resumed: Condvar::new(),
+        });
+
+        let thread = {
+            let shared = shared.clone();
+            thread::spawn(move || loop {
+                {
+                    let mut paused = shared.paused.lock().unwrap();
+                    while *paused && !shared.stop.load(Ordering::Relaxed) {
+                        paused = shared.resumed.wait(paused).unwrap();
+                    }
+                }
+                if shared.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let mut search = shared.search.lock().unwrap();
+                let (tree, state) = &mut *search;
+                if tree.is_solved(state) {
+                    // Nothing left to search, wait for the root to change or for the
+                    // search to stop. Both notify while holding the paused flag's lock,
+                    // so taking it before checking for a stop and releasing the tree
+                    // ensures that neither notification is missed. Other wake-ups go
+                    // back to checking the root.
+                    let paused = shared.paused.lock().unwrap();
+                    drop(search);
+                    if shared.stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let _paused = shared.resumed.wait(paused).unwrap();
+                    continue;
+                }
+                tree.step(state);
+            })
+        };
+
+        SearchHandle {
+            shared,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<G: Game, O: SearchObserver<G>, R: RngCore> MonteCarloTree<G, O, R> {
+    /// Whether the exact value of the given state is known.
+    fn is_solved(&self, state: &G) -> bool {
+        self.nodes
+            .get(&state.hash())
+            .is_some_and(|node| matches!(node.lock().unwrap().utility, Utility::Exact(_)))
+    }
+}
+
+impl<G: Game, O, R> SearchHandle<G, O, R> {
+    /// Asks the search to stop after its current step. This does not wait for the
+    /// thread to finish, see [SearchHandle::join] for that.
+    pub fn stop(&self) {
+        // The paused flag's lock is held so that a paused or idle thread cannot miss
+        // the notification.
+        let _paused = self.shared.paused.lock().unwrap();
+        self.shared.stop.store(true, Ordering::Relaxed);
+        self.shared.resumed.notify_all();
+    }
+
+    /// Whether the search thread is still running, which also includes being paused.
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Suspends the search after its current step, until [SearchHandle::resume] is
+    /// called.
+    pub fn pause(&self) {
+        *self.shared.paused.lock().unwrap() = true;
+    }
+
+    /// Resumes a paused search.
+    pub fn resume(&self) {
+        *self.shared.paused.lock().unwrap() = false;
+        self.shared.resumed.notify_all();
+    }
+
+    /// Whether the search is paused.
+    pub fn is_paused(&self) -> bool {
+        *self.shared.paused.lock().unwrap()
+    }
+
+    /// Gives access to the tree and to the root state between two steps of the
+    /// search, for instance to read its [statistics](MonteCarloTree::stats) or
+    /// [analysis](MonteCarloTree::analysis). The search waits until `f` returns.
+    pub fn with_tree<T>(&self, f: impl FnOnce(&mut MonteCarloTree<G, O, R>, &mut G) -> T) -> T {
+        let mut search = self.shared.search.lock().unwrap();
+        let (tree, state) = &mut *search;
+        f(tree, state)
+    }
+
+    /// Stops the search, waits for the thread to finish and gives back the tree
+    /// along with the root state.
+    pub fn join(mut self) -> (MonteCarloTree<G, O, R>, G) {
+        self.finish();
+        let shared = self.shared.clone();
+        drop(self);
+        match Arc::try_unwrap(shared) {
+            Ok(shared) => shared.search.into_inner().unwrap(),
+            Err(_) => unreachable!("the search thread should be finished"),
+        }
+    }
+
+    // Stops the search thread and waits for it. If it panicked, the tree's mutex is
+    // poisoned and the panic surfaces on the next access.
+    fn finish(&mut self) {
+        self.stop();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<G: Game, O: SearchObserver<G>, R: RngCore> SearchHandle<G, O, R> {
+    /// Returns the best action found so far, see [MonteCarloTree::best_action].
+    pub fn best_action(&self) -> Option<G::Action> {
+        self.with_tree(|tree, state| tree.best_action(state))
+    }
+
+    /// Plays an action on the root state, the search continuing from the resulting
+    /// position with the statistics gathered for it so far. Nodes which cannot be
+    /// reached anymore are discarded, see [MonteCarloTree::retain_subtree].
+    pub fn play(&self, action: &G::Action) {
+        self.with_tree(|tree, state| {
+            state.play(action);
+            tree.retain_subtree(state);
+            let _paused = self.shared.paused.lock().unwrap();
+            self.shared.resumed.notify_all();
+        });
+    }
+}
+
+impl<G: Game, O, R> Drop for SearchHandle<G, O, R> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{game::ExactUtility, testing::Subtraction};
+
+    // A subtraction game with a slow hash, widening the window between the search
+    // thread checking for a stop and waiting for the root to change.
+    #[derive(Clone)]
+    struct SlowHash(Subtraction);
+    impl Game for SlowHash {
+        type Action = u32;
+        type ActionsIter = Vec<u32>;
+        type Hash = (u32, u8);
+        type Player = u8;
+
+        fn play(&mut self, action: &u32) {
+            self.0.play(action)
+        }
+        fn undo(&mut self) {
+            self.0.undo()
+        }
+        fn current_player(&self) -> u8 {
+            self.0.current_player()
+        }
+        fn actions(&self) -> Vec<u32> {
+            self.0.actions()
+        }
+        fn utility(&self) -> Utility<Self> {
+            match self.0.utility() {
+                Utility::Exact(ExactUtility::Win(p)) => Utility::Exact(ExactUtility::Win(p)),
+                _ => Utility::Unknown,
+            }
+        }
+        fn hash(&self) -> (u32, u8) {
+            thread::sleep(Duration::from_micros(200));
+            self.0.hash()
+        }
+    }
+
+    #[test]
+    fn stop_wakes_solved_search() {
+        for _ in 0..20 {
+            let handle = MonteCarloTree::new().spawn(SlowHash(Subtraction::new(0)));
+            thread::sleep(Duration::from_millis(1));
+            handle.stop();
+            drop(handle);
+        }
+    }
+
+    #[test]
+    fn join_gives_back_tree() {
+        let handle = MonteCarloTree::new().spawn(Subtraction::new(5));
+        while !handle.with_tree(|tree, state| tree.is_solved(state)) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let (tree, mut state) = handle.join();
+        assert_eq!(tree.best_action(&mut state), Some(1));
+    }
+}
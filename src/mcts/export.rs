@@ -0,0 +1,309 @@
+//! # Tree export
+//! Dumps the part of the search tree reachable from a state, for debugging and
+//! visualisation, either as a [Graphviz](https://graphviz.org) DOT graph or as JSON.
+//!
+//! Values are always given for the side to move of each node, between -1 and 1.
+//! Nodes reached through several sequences of moves (transpositions) are only
+//! exported once: in DOT, every parent links to the same node, while in JSON later
+//! occurrences only carry the `id` of the first one and `"transposition": true`.
+//! The exception are nodes first reached at the maximum depth, which are exported
+//! again along with their children when reached at a shallower depth.
+//!
+//! ## JSON format
+//! Each node is an object with the following fields, children being nested:
+//! ```json
+//! {
+//!   "id": 0, "action": "4", "visits": 1200, "value": 0.43, "minimax": 0.51,
+//!   "exact": "won" | "lost" | "draw" | null, "children": [...]
+//! }
+//! ```
+//! The root has no `action`, and actions are formatted using their [Debug] impl.
+
+use rand::RngCore;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt::{Debug, Write as _},
+    io::{self, Write},
+};
+
+use super::{dequantize, MonteCarloTree, SearchObserver};
+use crate::game::{ExactUtility, Game, Utility};
+
+/// Parts of the tree to export.
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    /// Maximum depth of exported nodes, the root being at depth 0.
+    pub max_depth: Option<usize>,
+    /// Minimum number of visits of exported nodes, other than the root.
+    pub min_visits: u32,
+}
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: Some(3),
+            min_visits: 1,
+        }
+    }
+}
+
+// A node of the exported tree.
+struct ExportedNode {
+    id: usize,
+    action: Option<String>,
+    visits: u32,
+    value: Option<f32>,
+    minimax: f32,
+    exact: Option<&'static str>,
+    // Whether the node was already exported through another parent.
+    transposition: bool,
+    children: Vec<ExportedNode>,
+}
+
+impl<G: Game, O: SearchObserver<G>, R: RngCore> MonteCarloTree<G, O, R>
+where
+    G::Action: Debug,
+{
+    /// Writes the tree reachable from the given state as a Graphviz DOT graph, with
+    /// the visits, value and exact status of each node.
+    pub fn write_dot(
+        &self,
+        state: &mut G,
+        options: &ExportOptions,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        writeln!(writer, "digraph tree {{")?;
+        writeln!(writer, "  node [shape=box, fontname=monospace];")?;
+        if let Some(root) = self.export(state, options) {
+            write_dot_node(&mut writer, &root)?;
+        }
+        writeln!(writer, "}}")
+    }
+
+    /// Writes the tree reachable from the given state as JSON, in the format described
+    /// in the [module documentation](self).
+    pub fn write_json(
+        &self,
+        state: &mut G,
+        options: &ExportOptions,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        match self.export(state, options) {
+            Some(root) => {
+                let mut json = String::new();
+                write_json_node(&mut json, &root);
+                writeln!(writer, "{json}")
+            }
+            None => writeln!(writer, "null"),
+        }
+    }
+
+    /// Collects the exported part of the tree, or [None] if the state was never
+    /// visited.
+    fn export(&self, state: &mut G, options: &ExportOptions) -> Option<ExportedNode> {
+        self.export_node(
+            state,
+            None,
+            0,
+            options,
+            &mut HashMap::new(),
+            &mut HashSet::new(),
+        )
+    }
+
+    fn export_node(
+        &self,
+        state: &mut G,
+        action: Option<&G::Action>,
+        depth: usize,
+        options: &ExportOptions,
+        ids: &mut HashMap<G::Hash, usize>,
+        // Nodes exported with their children.
+        expanded: &mut HashSet<G::Hash>,
+    ) -> Option<ExportedNode> {
+        let (utility, visits, minimax) = {
+            let node = self.nodes.get(&state.hash())?.lock().unwrap();
+            (node.utility, node.visits, node.minimax)
+        };
+        if action.is_some() && visits < options.min_visits {
+            return None;
+        }
+
+        let hash = state.hash();
+        let seen = ids.contains_key(&hash);
+        let next_id = ids.len();
+        let id = *ids.entry(state.hash()).or_insert(next_id);
+        // Only nodes exported with their children count as already seen, so that
+        // nodes first reached at the maximum depth are expanded when reached again
+        // at a shallower depth.
+        let expand = options.max_depth.is_none_or(|max| depth < max);
+        let transposition = if expand { !expanded.insert(hash) } else { seen };
+        let current_player = state.current_player();
+        let mut exported = ExportedNode {
+            id,
+            action: action.map(|action| format!("{action:?}")),
+            visits,
+            value: match utility {
+                Utility::Approximate(approx) => Some(dequantize(approx)),
+                Utility::Exact(ExactUtility::Win(p)) if p == current_player => Some(1f32),
+                Utility::Exact(ExactUtility::Win(_)) => Some(-1f32),
+                Utility::Exact(ExactUtility::Draw) => Some(0f32),
+                Utility::Unknown => None,
+            },
+            minimax: dequantize(minimax),
+            exact: match utility {
+                Utility::Exact(ExactUtility::Win(p)) if p == current_player => Some("won"),
+                Utility::Exact(ExactUtility::Win(_)) => Some("lost"),
+                Utility::Exact(ExactUtility::Draw) => Some("draw"),
+                _ => None,
+            },
+            transposition,
+            children: vec![],
+        };
+
+        if !transposition && expand {
+            for action in state.actions() {
+                state.play(&action);
+                let child =
+                    self.export_node(state, Some(&action), depth + 1, options, ids, expanded);
+                state.undo();
+                exported.children.extend(child);
+            }
+            exported.children.sort_by_key(|child| Reverse(child.visits));
+        }
+        Some(exported)
+    }
+}
+
+fn write_dot_node(writer: &mut impl Write, node: &ExportedNode) -> io::Result<()> {
+    let mut label = format!("visits: {}", node.visits);
+    if let Some(value) = node.value {
+        write!(label, "\\nvalue: {value:.3}").unwrap();
+    }
+    write!(label, "\\nminimax: {:.3}", node.minimax).unwrap();
+    if let Some(exact) = node.exact {
+        write!(label, "\\n{exact}").unwrap();
+    }
+    let style = if node.exact.is_some() {
+        ", style=filled, fillcolor=lightgrey"
+    } else {
+        ""
+    };
+    writeln!(writer, "  n{} [label=\"{label}\"{style}];", node.id)?;
+
+    for child in &node.children {
+        let action = child.action.as_deref().unwrap_or_default();
+        writeln!(
+            writer,
+            "  n{} -> n{} [label=\"{}\"];",
+            node.id,
+            child.id,
+            escape_dot(action)
+        )?;
+        if !child.transposition {
+            write_dot_node(writer, child)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_json_node(json: &mut String, node: &ExportedNode) {
+    write!(json, "{{\"id\":{}", node.id).unwrap();
+    if let Some(action) = &node.action {
+        write!(json, ",\"action\":\"{}\"", escape_json(action)).unwrap();
+    }
+    if node.transposition {
+        json.push_str(",\"transposition\":true}");
+        return;
+    }
+    write!(json, ",\"visits\":{}", node.visits).unwrap();
+    match node.value {
+        Some(value) => write!(json, ",\"value\":{value}").unwrap(),
+        None => json.push_str(",\"value\":null"),
+    }
+    write!(json, ",\"minimax\":{}", node.minimax).unwrap();
+    match node.exact {
+        Some(exact) => write!(json, ",\"exact\":\"{exact}\"").unwrap(),
+        None => json.push_str(",\"exact\":null"),
+    }
+
+    json.push_str(",\"children\":[");
+    for (i, child) in node.children.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write_json_node(json, child);
+    }
+    json.push_str("]}");
+}
+
+/// Escapes a string for use in DOT quoted strings, dropping control characters
+/// which DOT has no escape sequences for.
+fn escape_dot(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes a string for use in JSON string literals.
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mcts::SearchConfig, testing::Subtraction};
+
+    #[test]
+    fn escapes_dot_and_json_strings() {
+        let s = "a\"b\\c\nd\u{7}";
+        assert_eq!(escape_dot(s), "a\\\"b\\\\c\\nd");
+        assert_eq!(escape_json(s), "a\\\"b\\\\c\\nd\\u0007");
+    }
+
+    #[test]
+    fn expands_nodes_first_reached_at_the_maximum_depth() {
+        let mut state = Subtraction::new(10);
+        let mut tree = MonteCarloTree::with_config(SearchConfig {
+            seed: Some(0),
+            ..Default::default()
+        });
+        for _ in 0..300 {
+            tree.step(&mut state);
+        }
+
+        // Taking 3 tokens at once leads to the node reached at the maximum depth by
+        // taking 1 token three times, which comes first.
+        let options = ExportOptions {
+            max_depth: Some(3),
+            min_visits: 1,
+        };
+        let root = tree.export(&mut state, &options).unwrap();
+        let child = root
+            .children
+            .iter()
+            .find(|child| child.action.as_deref() == Some("3"))
+            .unwrap();
+        assert!(!child.transposition);
+        assert!(!child.children.is_empty());
+    }
+}
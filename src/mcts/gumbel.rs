@@ -0,0 +1,183 @@
+//! # Gumbel root search
+//! With small simulation budgets, UCT spends most of its root visits making sure
+//! every action gets explored. Gumbel search ([Danihelka et al., 2022](https://openreview.net/forum?id=bERaNdoegnO))
+//! instead samples the actions to consider without replacement using the Gumbel-top-k
+//! trick over the priors, then splits the budget among them with Sequential Halving:
+//! the budget is divided into phases, at the end of each the worse half of the
+//! remaining actions is discarded.
+//!
+//! Actions are compared using `g(a) + logit(a) + σ(q(a))`, where `g` is the Gumbel
+//! noise, `logit` the log-prior of the action and `σ` a monotonic transformation of
+//! the action's value which grows with the number of visits.
+
+use rand::{Rng, RngCore};
+
+use super::{MonteCarloTree, RootSearch, SearchObserver};
+use crate::game::{ExactUtility, Game, Utility};
+
+// Parameters of the σ transformation of values.
+const VISIT_OFFSET: f32 = 50f32;
+const VALUE_SCALE: f32 = 1f32;
+
+/// Sequential Halving state of the current root.
+pub(super) struct SequentialHalving<G: Game> {
+    root: G::Hash,
+    actions: usize,
+    // Remaining candidates, as indices in the order of [Game::actions], along with
+    // their Gumbel-perturbed logits.
+    candidates: Vec<(usize, f32)>,
+    // Visits given to each remaining candidate during the current phase.
+    phase_visits: Vec<u32>,
+    visits_per_candidate: u32,
+    phases: u32,
+    simulations: u32,
+}
+
+impl<G: Game, O: SearchObserver<G>, R: RngCore> MonteCarloTree<G, O, R> {
+    /// Picks the root action to simulate according to the Sequential Halving
+    /// schedule, as an index in the order of [Game::actions].
+    pub(super) fn gumbel_root_action(
+        &mut self,
+        state: &mut G,
+        actions: &[G::Action],
+        priors: Option<&[f32]>,
+    ) -> Option<usize> {
+        let RootSearch::Gumbel {
+            considered,
+            simulations,
+        } = self.config.root_search
+        else {
+            return None;
+        };
+        if actions.is_empty() {
+            return None;
+        }
+
+        // Start a new schedule whenever the root changes.
+        let hash = state.hash();
+        if !self
+            .gumbel
+            .as_ref()
+            .is_some_and(|halving| halving.root == hash && halving.actions == actions.len())
+        {
+            let uniform_prior = 1f32 / actions.len() as f32;
+            let mut candidates = (0..actions.len())
+                .map(|i| {
+                    let prior = priors
+                        .and_then(|priors| priors.get(i).copied())
+                        .unwrap_or(uniform_prior);
+                    let uniform: f32 = self.rng.gen_range(f32::EPSILON..1f32);
+                    let gumbel = -(-uniform.ln()).ln();
+                    (i, gumbel + prior.max(f32::MIN_POSITIVE).ln())
+                })
+                .collect::<Vec<_>>();
+            candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+            candidates.truncate(considered.max(1));
+
+            let phases = (candidates.len() as f32).log2().ceil().max(1f32) as u32;
+            self.gumbel = Some(SequentialHalving {
+                root: hash,
+                actions: actions.len(),
+                phase_visits: vec![0; candidates.len()],
+                visits_per_candidate: (simulations / (phases * candidates.len() as u32)).max(1),
+                candidates,
+                phases,
+                simulations,
+            });
+        }
+
+        let halving = self.gumbel.as_ref().unwrap();
+        if halving.candidates.len() > 1
+            && halving
+                .phase_visits
+                .iter()
+                .all(|v| *v >= halving.visits_per_candidate)
+        {
+            // The phase is over, discard the worse half of the candidates.
+            let max_visits = self.max_visits(state);
+            let mut candidates = halving
+                .candidates
+                .iter()
+                .map(|&(i, logit)| {
+                    let score = self.gumbel_score(state, &actions[i], logit, max_visits);
+                    (i, logit, score)
+                })
+                .collect::<Vec<_>>();
+            candidates.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+            candidates.truncate(candidates.len().div_ceil(2));
+
+            let halving = self.gumbel.as_mut().unwrap();
+            halving.candidates = candidates.into_iter().map(|(i, l, _)| (i, l)).collect();
+            halving.phase_visits = vec![0; halving.candidates.len()];
+            halving.visits_per_candidate =
+                (halving.simulations / (halving.phases * halving.candidates.len() as u32)).max(1);
+        }
+
+        // Visit the candidate with the fewest visits during this phase.
+        let halving = self.gumbel.as_mut().unwrap();
+        let (candidate, visits) = halving
+            .phase_visits
+            .iter_mut()
+            .enumerate()
+            .min_by_key(|(_, v)| **v)
+            .unwrap();
+        *visits += 1;
+        Some(halving.candidates[candidate].0)
+    }
+
+    /// Returns the best root action according to Gumbel search, if the given state
+    /// is the root of the current Sequential Halving schedule.
+    pub(super) fn gumbel_best_action(&self, state: &mut G) -> Option<G::Action> {
+        let halving = self.gumbel.as_ref()?;
+        if halving.root != state.hash() {
+            return None;
+        }
+
+        let mut actions = state.actions().into_iter().map(Some).collect::<Vec<_>>();
+        if actions.len() != halving.actions {
+            return None;
+        }
+        let max_visits = self.max_visits(state);
+        let (best, _) = halving
+            .candidates
+            .iter()
+            .map(|&(i, logit)| {
+                let action = actions[i].as_ref().unwrap();
+                (i, self.gumbel_score(state, action, logit, max_visits))
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        actions[best].take()
+    }
+
+    /// Returns the highest number of visits among the children of a node.
+    fn max_visits(&self, state: &mut G) -> u32 {
+        self.visit_counts(state)
+            .into_iter()
+            .map(|(_, visits)| visits)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Computes `g(a) + logit(a) + σ(q(a))` for a root action, `logit` being the
+    /// Gumbel-perturbed logit of the action.
+    fn gumbel_score(&self, state: &mut G, action: &G::Action, logit: f32, max_visits: u32) -> f32 {
+        let current_player = state.current_player();
+
+        state.play(action);
+        let value = self.nodes.get(&state.hash()).map_or(0f32, |child| {
+            let child = child.lock().unwrap();
+            match child.utility {
+                Utility::Exact(ExactUtility::Win(p)) if p == current_player => 1f32,
+                Utility::Exact(ExactUtility::Win(_)) => -1f32,
+                Utility::Exact(ExactUtility::Draw) => 0f32,
+                Utility::Approximate(_) => {
+                    self.exploitation(&child, state.current_player() == current_player)
+                }
+                Utility::Unknown => 0f32,
+            }
+        });
+        state.undo();
+
+        logit + (VISIT_OFFSET + max_visits as f32) * VALUE_SCALE * value
+    }
+}
@@ -0,0 +1,56 @@
+//! # Hybrid MCTS-minimax
+//! Random playouts and averaged values easily miss shallow tactics: a position
+//! where one move wins on the spot can look even, and a move walking into a forced
+//! loss can look good until it is visited many times. Following Baier and Winands'
+//! MCTS-minimax hybrids, shallow minimax searches are embedded in the tree search:
+//! - before a leaf is evaluated, a search for a forced win of either player proves
+//!   it directly, see [SearchConfig::expansion_minimax](super::SearchConfig::expansion_minimax).
+//! - at every step of a playout, a winning action is played whenever a search finds
+//!   one, see [SearchConfig::playout_minimax](super::SearchConfig::playout_minimax).
+//!
+//! Both only look for wins and losses, so the searches need no evaluation and stay
+//! cheap at small depths. Their cost still grows exponentially with the depth.
+
+use crate::game::{ExactUtility, Game, Utility};
+
+/// Returns the player who can force a win from the given state within `depth`
+/// plies, if any.
+pub(super) fn forced_winner<G: Game>(state: &mut G, depth: u32) -> Option<G::Player> {
+    match state.utility() {
+        Utility::Exact(ExactUtility::Win(player)) => return Some(player),
+        Utility::Unknown if depth > 0 => {}
+        _ => return None,
+    }
+
+    let player = state.current_player();
+    // Winner of every action searched so far, if they all lose.
+    let mut winner = None;
+    let mut all_lose = true;
+    for (i, action) in state.actions().into_iter().enumerate() {
+        state.play(&action);
+        let child = forced_winner(state, depth - 1);
+        state.undo();
+
+        match child {
+            Some(p) if p == player => return Some(player),
+            Some(p) if i == 0 || winner == Some(p) => winner = Some(p),
+            _ => all_lose = false,
+        }
+    }
+    winner.filter(|_| all_lose)
+}
+
+/// Returns an action forcing a win of the side to move within `depth` plies, if
+/// there is one.
+pub(super) fn winning_action<G: Game>(state: &mut G, depth: u32) -> Option<G::Action> {
+    if depth == 0 {
+        return None;
+    }
+    let player = state.current_player();
+    state.actions().into_iter().find(|action| {
+        state.play(action);
+        let winner = forced_winner(state, depth - 1);
+        state.undo();
+        winner == Some(player)
+    })
+}
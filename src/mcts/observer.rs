@@ -0,0 +1,36 @@
+//! # Search observers
+//! Hooks into the search loop, for telemetry, progress reporting or debugging.
+//!
+//! Trees take their observer as a type parameter, defaulting to `()` which ignores
+//! every event: since all callbacks have empty default implementations, calls to
+//! them are optimized away entirely when no observer is set.
+
+use crate::game::{ExactUtility, Game, Utility};
+
+/// Receives the events of a search, see [MonteCarloTree::with_observer](super::MonteCarloTree::with_observer).
+///
+/// Values are given between -1 and 1, and states are passed as they are when the
+/// event happens.
+#[allow(unused_variables)]
+pub trait SearchObserver<G: Game> {
+    /// Called at the start of each call to [MonteCarloTree::step](super::MonteCarloTree::step),
+    /// with the root state.
+    fn iteration_started(&mut self, root: &G) {}
+
+    /// Called when a node is added to the tree, with its utility.
+    fn node_expanded(&mut self, state: &G, utility: Utility<G>) {}
+
+    /// Called at the end of each playout, with its length in plies and its result
+    /// for the side to move of the state it started from.
+    fn playout_finished(&mut self, state: &G, length: u32, result: f32) {}
+
+    /// Called for each node updated during backpropagation, with the result of the
+    /// simulation for its side to move and its new number of visits.
+    fn backpropagated(&mut self, state: &G, value: f32, visits: u32) {}
+
+    /// Called when the exact value of a node is proven from those of its children,
+    /// or by a minimax search on expansion.
+    fn node_proven(&mut self, state: &G, utility: ExactUtility<G>) {}
+}
+
+impl<G: Game> SearchObserver<G> for () {}
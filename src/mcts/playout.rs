@@ -0,0 +1,167 @@
+//! # Playout policies
+//! Domain-independent enhancements of random playouts, as used by general game
+//! playing programs such as CadiaPlayer:
+//! - Move-Average Sampling Technique (MAST) keeps the average result of the
+//!   simulations in which each action was played, and biases playouts towards
+//!   actions with good averages.
+//! - N-gram Selection Technique (NST) does the same for sequences of consecutive
+//!   actions, so that the value of an action can depend on the actions preceding it.
+//!
+//! Statistics are learned from the results of playouts and are kept for the whole
+//! lifetime of the tree, so that knowledge carries over from one move to the next.
+//! Actions are identified by their [key](Game::action_key), games without keys
+//! thus get uniform playouts.
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng, RngCore};
+use std::collections::HashMap;
+
+use super::{MonteCarloTree, MoveSelection, PlayoutPolicy, SearchObserver};
+use crate::game::Game;
+
+// Value of actions that were never played, optimistic so that they get tried.
+const UNKNOWN_VALUE: f32 = 1f32;
+
+// A sequence of action keys, along with the player of the last one.
+type NGram<G> = (<G as Game>::Player, Vec<u64>);
+
+/// Statistics learned from playouts, as a number of samples and the sum of their
+/// results for the player of the last action.
+pub(super) struct PlayoutStatistics<G: Game> {
+    mast: HashMap<(G::Player, u64), (f32, f32)>,
+    // N-grams of at least two actions.
+    ngrams: HashMap<NGram<G>, (f32, f32)>,
+}
+impl<G: Game> Default for PlayoutStatistics<G> {
+    fn default() -> Self {
+        Self {
+            mast: HashMap::new(),
+            ngrams: HashMap::new(),
+        }
+    }
+}
+
+impl<G: Game> PlayoutStatistics<G> {
+    /// Score of an action in a playout: the average of the values of the n-grams
+    /// ending with it, up to the given length. N-grams of more than one action are
+    /// only considered once they have been sampled `threshold` times.
+    fn score(
+        &self,
+        player: G::Player,
+        key: u64,
+        history: &[(G::Player, u64)],
+        length: usize,
+        threshold: u32,
+    ) -> f32 {
+        let Some((samples, sum)) = self.mast.get(&(player, key)) else {
+            return UNKNOWN_VALUE;
+        };
+
+        let mut total = sum / samples;
+        let mut count = 1;
+        for n in 2..=length.min(history.len() + 1) {
+            let ngram = history[history.len() + 1 - n..]
+                .iter()
+                .map(|(_, k)| *k)
+                .chain([key])
+                .collect();
+            match self.ngrams.get(&(player, ngram)) {
+                Some((samples, sum)) if *samples >= threshold as f32 => {
+                    total += sum / samples;
+                    count += 1;
+                }
+                _ => break,
+            }
+        }
+        total / count as f32
+    }
+}
+
+impl<G: Game, O: SearchObserver<G>, R: RngCore> MonteCarloTree<G, O, R> {
+    /// Picks the next action of a playout according to the playout policy, given
+    /// the keys of the moves played since the beginning of the playout.
+    pub(super) fn playout_action(
+        &mut self,
+        state: &G,
+        history: &[(G::Player, u64)],
+    ) -> Option<G::Action> {
+        let mut actions = state.actions().into_iter().collect::<Vec<_>>();
+        if actions.is_empty() {
+            return None;
+        }
+        let rng = &mut self.rng;
+        let (length, threshold, selection) = match self.config.playout_policy {
+            PlayoutPolicy::Uniform => {
+                let i = rng.gen_range(0..actions.len());
+                return Some(actions.swap_remove(i));
+            }
+            PlayoutPolicy::Mast { selection } => (1, 0, selection),
+            PlayoutPolicy::Nst {
+                length,
+                threshold,
+                selection,
+            } => (length, threshold, selection),
+        };
+
+        let player = state.current_player();
+        let scores = actions
+            .iter()
+            .map(|action| match state.action_key(action) {
+                Some(key) => self
+                    .playout_statistics
+                    .score(player, key, history, length, threshold),
+                None => UNKNOWN_VALUE,
+            })
+            .collect::<Vec<_>>();
+
+        let i = match selection {
+            MoveSelection::EpsilonGreedy { epsilon } if rng.gen::<f32>() >= epsilon => scores
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(i, _)| i)
+                .unwrap(),
+            MoveSelection::EpsilonGreedy { .. } => rng.gen_range(0..actions.len()),
+            MoveSelection::Gibbs { temperature } => {
+                let weights = scores
+                    .iter()
+                    .map(|score| (score / temperature).exp())
+                    .collect::<Vec<_>>();
+                WeightedIndex::new(&weights)
+                    .map(|distribution| distribution.sample(rng))
+                    .unwrap_or_else(|_| rng.gen_range(0..actions.len()))
+            }
+        };
+        Some(actions.swap_remove(i))
+    }
+
+    /// Updates playout statistics with the keys of the moves of a playout and its
+    /// result, given for `player`.
+    pub(super) fn update_playout_statistics(
+        &mut self,
+        moves: &[(G::Player, u64)],
+        player: G::Player,
+        result: f32,
+    ) {
+        let length = match self.config.playout_policy {
+            PlayoutPolicy::Uniform => return,
+            PlayoutPolicy::Mast { .. } => 1,
+            PlayoutPolicy::Nst { length, .. } => length,
+        };
+
+        let statistics = &mut self.playout_statistics;
+        for (j, (p, key)) in moves.iter().enumerate() {
+            let value = if *p == player { result } else { -result };
+
+            let (samples, sum) = statistics.mast.entry((*p, *key)).or_insert((0f32, 0f32));
+            *samples += 1f32;
+            *sum += value;
+
+            for n in 2..=length.min(j + 1) {
+                let ngram = moves[j + 1 - n..=j].iter().map(|(_, k)| *k).collect();
+                let (samples, sum) = statistics.ngrams.entry((*p, ngram)).or_insert((0f32, 0f32));
+                *samples += 1f32;
+                *sum += value;
+            }
+        }
+    }
+}
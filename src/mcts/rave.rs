@@ -0,0 +1,93 @@
+//! # Rapid Action Value Estimation
+//! All-moves-as-first (AMAF) statistics consider that an action played at any
+//! point of a simulation by the side to move of a node could have been played
+//! first. Each simulation thus updates the statistics of many actions at once,
+//! giving quick but biased estimates of their value. RAVE blends these estimates
+//! with the regular values of children, relying on them less and less as children
+//! get visited.
+//!
+//! Actions are identified by their [key](Game::action_key), games without keys
+//! thus get no AMAF statistics.
+
+use rand::RngCore;
+use std::collections::HashSet;
+
+use super::{MonteCarloNode, MonteCarloTree, RaveSchedule, SearchObserver};
+use crate::game::Game;
+
+impl RaveSchedule {
+    /// Weight of the AMAF value of an action, given the number of visits of the
+    /// child it leads to and the number of AMAF samples of the action.
+    fn beta(&self, visits: f32, samples: f32) -> f32 {
+        match *self {
+            Self::Equivalence { k } => (k / (3f32 * visits + k)).sqrt(),
+            Self::MinimumMse { bias } => {
+                samples / (visits + samples + 4f32 * bias * bias * visits * samples)
+            }
+        }
+    }
+}
+
+impl<G: Game, O: SearchObserver<G>, R: RngCore> MonteCarloTree<G, O, R> {
+    /// Blends the value of a child with the AMAF statistics of the action leading
+    /// to it, if RAVE is enabled.
+    pub(super) fn rave_exploitation(
+        &self,
+        exploitation: f32,
+        visits: u32,
+        amaf: Option<(f32, f32)>,
+    ) -> f32 {
+        let (Some(schedule), Some(value)) = (self.config.rave, self.amaf_value(amaf)) else {
+            return exploitation;
+        };
+
+        let beta = schedule.beta(visits as f32, amaf.unwrap().0);
+        (1f32 - beta) * exploitation + beta * value
+    }
+
+    /// Returns the AMAF value of an action, if RAVE is enabled and the action has
+    /// samples. Children not visited yet are valued by it alone, as if β was 1.
+    pub(super) fn amaf_value(&self, amaf: Option<(f32, f32)>) -> Option<f32> {
+        match (self.config.rave, amaf) {
+            (Some(_), Some((samples, sum))) if samples > 0f32 => Some(sum / samples),
+            _ => None,
+        }
+    }
+
+    /// Updates the AMAF statistics of the node of the given state, given the actions
+    /// played after it in the tree and the statistics of the playouts.
+    ///
+    /// The leaf value is given for the side to move of the leaf.
+    pub(super) fn update_amaf(
+        &self,
+        node: &mut MonteCarloNode<G>,
+        state: &G,
+        moves: &[(G::Player, G::Action)],
+        leaf_player: G::Player,
+        leaf_value: f32,
+    ) {
+        let player = state.current_player();
+        let sign = if player == leaf_player { 1f32 } else { -1f32 };
+
+        // Actions played in the tree come first in every simulation.
+        let mut seen = HashSet::new();
+        for (_, action) in moves.iter().filter(|(p, _)| *p == player) {
+            let Some(key) = state.action_key(action) else {
+                continue;
+            };
+            if seen.insert(key) {
+                let (samples, sum) = node.amaf.entry(key).or_insert((0f32, 0f32));
+                *samples += 1f32;
+                *sum += sign * leaf_value;
+            }
+        }
+
+        for ((p, key), (playout_samples, playout_sum)) in &self.playout_amaf {
+            if *p == player && !seen.contains(key) {
+                let (samples, sum) = node.amaf.entry(*key).or_insert((0f32, 0f32));
+                *samples += playout_samples;
+                *sum += sign * playout_sum;
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! # Time-budgeted search
+//! Searching for a move under a time control, the time being allocated by a
+//! [TimeManager].
+
+use rand::RngCore;
+use std::time::Instant;
+
+use super::{MonteCarloTree, SearchObserver};
+use crate::{
+    game::{ExactUtility, Game, Utility},
+    time::{Clock, SearchProgress, TimeManager},
+};
+
+// Number of steps between two checks of the search progress.
+const CHECK_INTERVAL: u32 = 16;
+
+impl<G: Game, O: SearchObserver<G>, R: RngCore> MonteCarloTree<G, O, R> {
+    /// Searches the given state for as long as the time manager allows under the
+    /// given clock, and returns the best action found. With a fixed number of
+    /// iterations, exactly that many steps are made, and with an infinite clock the
+    /// search only stops once the best action is proven.
+    pub fn search(
+        &mut self,
+        state: &mut G,
+        clock: &Clock,
+        time: &TimeManager,
+    ) -> Option<G::Action> {
+        if let Clock::Iterations(iterations) = *clock {
+            for _ in 0..iterations {
+                self.step(state);
+            }
+            return self.best_action(state);
+        }
+        let budget = time.allocate(clock);
+        let start = Instant::now();
+        let mut progress = SearchProgress::default();
+        let mut best = None;
+
+        loop {
+            for _ in 0..CHECK_INTERVAL {
+                self.step(state);
+            }
+            progress.iterations += CHECK_INTERVAL as u64;
+            progress.elapsed = start.elapsed();
+
+            let action = self.best_action(state);
+            if action != best {
+                best = action;
+                progress.best_changed = progress.elapsed;
+            }
+            let Some(action) = &best else {
+                // Terminal states have no move to search.
+                return None;
+            };
+            self.root_progress(state, action, &mut progress);
+
+            if time.should_stop(budget.as_ref(), &progress) {
+                return best;
+            }
+        }
+    }
+
+    /// Fills in the visits of the best and second best root children, and whether the
+    /// best action is proven.
+    fn root_progress(&self, state: &mut G, best: &G::Action, progress: &mut SearchProgress) {
+        let current_player = state.current_player();
+        progress.second_visits = 0;
+        progress.proven = self
+            .nodes
+            .get(&state.hash())
+            .is_some_and(|node| matches!(node.lock().unwrap().utility, Utility::Exact(_)));
+
+        for action in state.actions() {
+            state.play(&action);
+            if let Some(child) = self.nodes.get(&state.hash()) {
+                let child = child.lock().unwrap();
+                if action == *best {
+                    progress.best_visits = child.visits;
+                    progress.proven |= matches!(
+                        child.utility,
+                        Utility::Exact(ExactUtility::Win(p)) if p == current_player
+                    );
+                } else {
+                    progress.second_visits = progress.second_visits.max(child.visits);
+                }
+            }
+            state.undo();
+        }
+    }
+}
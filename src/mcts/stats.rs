@@ -0,0 +1,97 @@
+//! # Search statistics
+//! Counters kept by the tree across calls to [MonteCarloTree::step], to tune the
+//! search and compare engine builds.
+
+use rand::RngCore;
+use std::{mem::size_of, sync::Mutex, time::Duration};
+
+use super::{MonteCarloNode, MonteCarloTree, SearchObserver};
+use crate::game::Game;
+
+/// A snapshot of the statistics of a tree, see [MonteCarloTree::stats].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchStatistics {
+    /// Number of calls to [MonteCarloTree::step].
+    pub iterations: u64,
+    /// Number of nodes added to the tree.
+    pub nodes_created: u64,
+    /// Number of nodes whose exact value was proven from those of their children,
+    /// or by a minimax search on expansion.
+    pub proven_nodes: u64,
+    /// Greatest number of moves played during a selection phase.
+    pub max_depth: u32,
+    /// Sum of the number of moves played during selection phases.
+    pub total_depth: u64,
+    /// Number of playouts.
+    pub playouts: u64,
+    /// Sum of the number of moves played during playouts.
+    pub playout_plies: u64,
+    /// Time spent in [MonteCarloTree::step].
+    pub elapsed: Duration,
+    /// Estimate of the memory used by the tree, in bytes. Unlike the other counters,
+    /// this is not reset.
+    pub memory: usize,
+}
+impl SearchStatistics {
+    /// Average number of moves played during selection phases.
+    pub fn average_depth(&self) -> f32 {
+        self.total_depth as f32 / self.iterations.max(1) as f32
+    }
+
+    /// Average number of moves played during playouts.
+    pub fn average_playout_length(&self) -> f32 {
+        self.playout_plies as f32 / self.playouts.max(1) as f32
+    }
+
+    /// Number of iterations per second spent searching.
+    pub fn iterations_per_second(&self) -> f32 {
+        self.iterations as f32 / self.elapsed.as_secs_f32().max(f32::EPSILON)
+    }
+
+    /// Number of playouts per second spent searching.
+    pub fn playouts_per_second(&self) -> f32 {
+        self.playouts as f32 / self.elapsed.as_secs_f32().max(f32::EPSILON)
+    }
+}
+
+impl<G: Game, O: SearchObserver<G>, R: RngCore> MonteCarloTree<G, O, R> {
+    /// Returns the statistics of the search since the tree was created or since
+    /// the last call to [MonteCarloTree::reset_stats].
+    pub fn stats(&self) -> SearchStatistics {
+        SearchStatistics {
+            memory: self.memory(),
+            ..self.stats
+        }
+    }
+
+    /// Resets the statistics of the search, typically between two moves.
+    pub fn reset_stats(&mut self) {
+        self.stats = SearchStatistics::default();
+    }
+
+    /// Estimates the memory used by the nodes of the tree.
+    fn memory(&self) -> usize {
+        // Each entry stores the hash and a pointer to the node, along with a control
+        // byte. Nodes are allocated with their reference counts.
+        let entry = size_of::<G::Hash>() + size_of::<usize>() + 1;
+        let node = size_of::<Mutex<MonteCarloNode<G>>>() + 2 * size_of::<usize>();
+        let amaf_entry = size_of::<u64>() + size_of::<(f32, f32)>() + 1;
+
+        self.nodes.capacity() * entry
+            + self
+                .nodes
+                .values()
+                .map(|node| node.lock().unwrap())
+                .map(|node| {
+                    node.priors.as_ref().map_or(0, |priors| priors.capacity()) * size_of::<f32>()
+                        + node.amaf.capacity() * amaf_entry
+                        + node
+                            .ranking
+                            .as_ref()
+                            .map_or(0, |ranking| ranking.capacity())
+                            * size_of::<usize>()
+                })
+                .sum::<usize>()
+            + self.nodes.len() * node
+    }
+}
@@ -0,0 +1,395 @@
+//! # Neural network evaluation
+//! A small policy/value network evaluated on the CPU, in the spirit of AlphaZero.
+//! Combined with [Selection::Puct](crate::mcts::Selection::Puct), the policy head
+//! guides the search while the value head replaces random playouts.
+//!
+//! The network is a multi-layer perceptron made of a shared trunk followed by two
+//! heads:
+//! - the **policy head** outputs one logit per action index (see [TensorEncoding]).
+//!   Logits of illegal actions are masked out before applying a softmax.
+//! - the **value head** outputs a single value, squashed with `tanh`, giving the
+//!   expected result for the side to move.
+//!
+//! Hidden layers use ReLU activations, the last layer of each head is linear.
+//!
+//! ## File format
+//! Weights are stored in a simple little-endian binary format:
+//! - the magic bytes `CHNN` followed by the format version as a `u32` (currently 1).
+//! - three sections (trunk, policy head, value head), each starting with its number
+//!   of layers as a `u32`. Each layer is then given as its number of inputs and
+//!   outputs (`u32`s), followed by its weights as `outputs × inputs` row-major `f32`s
+//!   and its biases as `outputs` `f32`s.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    marker::PhantomData,
+    path::Path,
+};
+
+use crate::{
+    binary::{invalid_data, read_f32s, read_header, read_u32, write_header},
+    evaluator::{Evaluation, Evaluator, TensorEncoding},
+};
+
+const MAGIC: &[u8; 4] = b"CHNN";
+const VERSION: u32 = 1;
+// Largest number of weights of a layer read from a file.
+const MAX_LAYER_SIZE: usize = 1 << 26;
+
+/// A fully-connected layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    inputs: usize,
+    outputs: usize,
+    // Row-major weights, one row per output.
+    weights: Vec<f32>,
+    biases: Vec<f32>,
+}
+impl Layer {
+    /// Creates a layer from its row-major weights (one row of `inputs` weights per
+    /// output) and biases.
+    ///
+    /// Returns [None] if the layer is empty or if the dimensions do not match.
+    pub fn new(inputs: usize, outputs: usize, weights: Vec<f32>, biases: Vec<f32>) -> Option<Self> {
+        if inputs == 0
+            || outputs == 0
+            || weights.len() != inputs * outputs
+            || biases.len() != outputs
+        {
+            return None;
+        }
+        Some(Self {
+            inputs,
+            outputs,
+            weights,
+            biases,
+        })
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// Computes the output of this layer, optionally applying a ReLU activation.
+    fn forward(&self, input: &[f32], relu: bool) -> Vec<f32> {
+        self.weights
+            .chunks_exact(self.inputs)
+            .zip(&self.biases)
+            .map(|(row, bias)| {
+                let output = row.iter().zip(input).map(|(w, x)| w * x).sum::<f32>() + bias;
+                if relu {
+                    output.max(0f32)
+                } else {
+                    output
+                }
+            })
+            .collect()
+    }
+}
+
+/// A policy/value multi-layer perceptron.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Network {
+    trunk: Vec<Layer>,
+    policy: Vec<Layer>,
+    value: Vec<Layer>,
+}
+impl Network {
+    /// Assembles a network from its layers.
+    ///
+    /// Returns [None] if the heads are empty, if the layers' dimensions do not chain
+    /// together or if the value head does not output a single value.
+    pub fn new(trunk: Vec<Layer>, policy: Vec<Layer>, value: Vec<Layer>) -> Option<Self> {
+        let chains = |layers: &[Layer]| layers.windows(2).all(|w| w[0].outputs == w[1].inputs);
+        let trunk_outputs = trunk.last().map(|l| l.outputs);
+        let head_fits = |head: &[Layer]| match (trunk_outputs, head.first()) {
+            (Some(outputs), Some(first)) => outputs == first.inputs,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        if !chains(&trunk)
+            || !chains(&policy)
+            || !chains(&value)
+            || !head_fits(&policy)
+            || !head_fits(&value)
+            || value.last().map(|l| l.outputs) != Some(1)
+            || policy[0].inputs != value[0].inputs
+        {
+            return None;
+        }
+
+        Some(Self {
+            trunk,
+            policy,
+            value,
+        })
+    }
+
+    /// Size of the inputs expected by the network.
+    pub fn input_size(&self) -> usize {
+        self.trunk.first().unwrap_or(&self.policy[0]).inputs
+    }
+
+    /// Size of the policy output by the network.
+    pub fn policy_size(&self) -> usize {
+        self.policy.last().unwrap().outputs
+    }
+
+    /// Runs the network, returning the policy logits and the value.
+    ///
+    /// Panics if the input is not of size [Network::input_size].
+    pub fn forward(&self, input: &[f32]) -> (Vec<f32>, f32) {
+        assert_eq!(input.len(), self.input_size(), "invalid network input size");
+        let hidden = self
+            .trunk
+            .iter()
+            .fold(input.to_vec(), |x, layer| layer.forward(&x, true));
+        let head = |layers: &[Layer]| {
+            let (last, hidden_layers) = layers.split_last().unwrap();
+            let x = hidden_layers
+                .iter()
+                .fold(hidden.clone(), |x, layer| layer.forward(&x, true));
+            last.forward(&x, false)
+        };
+
+        (head(&self.policy), head(&self.value)[0].tanh())
+    }
+
+    /// Loads a network from a weights file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Saves the network to a weights file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Reads a network in the format described in the [module documentation](self).
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        read_header(reader, MAGIC, VERSION, "network weights")?;
+
+        let mut read_section = || -> io::Result<Vec<Layer>> {
+            (0..read_u32(reader)?)
+                .map(|_| {
+                    let inputs = read_u32(reader)? as usize;
+                    let outputs = read_u32(reader)? as usize;
+                    let size = inputs
+                        .checked_mul(outputs)
+                        .filter(|&size| size > 0 && size <= MAX_LAYER_SIZE)
+                        .ok_or_else(|| invalid_data("invalid layer dimensions"))?;
+                    let weights = read_f32s(reader, size)?;
+                    let biases = read_f32s(reader, outputs)?;
+                    Ok(Layer::new(inputs, outputs, weights, biases).unwrap())
+                })
+                .collect()
+        };
+        let trunk = read_section()?;
+        let policy = read_section()?;
+        let value = read_section()?;
+
+        Self::new(trunk, policy, value).ok_or_else(|| invalid_data("mismatched layer dimensions"))
+    }
+
+    /// Writes the network in the format described in the [module documentation](self).
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_header(writer, MAGIC, VERSION)?;
+        for section in [&self.trunk, &self.policy, &self.value] {
+            writer.write_all(&(section.len() as u32).to_le_bytes())?;
+            for layer in section {
+                writer.write_all(&(layer.inputs as u32).to_le_bytes())?;
+                writer.write_all(&(layer.outputs as u32).to_le_bytes())?;
+                for x in layer.weights.iter().chain(&layer.biases) {
+                    writer.write_all(&x.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An [Evaluator] backed by a policy/value [Network], whose dimensions match the
+/// [TensorEncoding] of the game.
+///
+/// States are encoded using [TensorEncoding::to_tensor], and the policy is masked
+/// to the actions returned by [Game::actions](crate::game::Game::actions).
+/// Evaluating a state panics if one of its actions has an
+/// [index](TensorEncoding::action_index) outside of the policy.
+pub struct NeuralEvaluator<G: TensorEncoding> {
+    network: Network,
+    game: PhantomData<fn(&G)>,
+}
+impl<G: TensorEncoding> NeuralEvaluator<G> {
+    /// Creates an evaluator from a network.
+    ///
+    /// Returns [None] if the network does not take inputs of size
+    /// [TensorEncoding::TENSOR_SIZE] or does not output policies of size
+    /// [TensorEncoding::POLICY_SIZE].
+    pub fn new(network: Network) -> Option<Self> {
+        if network.input_size() != G::TENSOR_SIZE || network.policy_size() != G::POLICY_SIZE {
+            return None;
+        }
+        Some(Self {
+            network,
+            game: PhantomData,
+        })
+    }
+
+    /// Loads the network weights from a file, checking their dimensions like
+    /// [NeuralEvaluator::new].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(Network::load(path)?)
+            .ok_or_else(|| invalid_data("network dimensions do not match the game"))
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+}
+impl<G: TensorEncoding> Clone for NeuralEvaluator<G> {
+    fn clone(&self) -> Self {
+        Self {
+            network: self.network.clone(),
+            game: PhantomData,
+        }
+    }
+}
+impl<G: TensorEncoding> fmt::Debug for NeuralEvaluator<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NeuralEvaluator")
+            .field("network", &self.network)
+            .finish()
+    }
+}
+impl<G: TensorEncoding> Evaluator<G> for NeuralEvaluator<G> {
+    fn evaluate(&mut self, state: &G) -> Evaluation {
+        let (logits, value) = self.network.forward(&state.to_tensor());
+
+        // Mask the policy to legal actions, then apply a softmax.
+        let mut priors = state
+            .actions()
+            .into_iter()
+            .map(|action| {
+                let index = state.action_index(&action);
+                assert!(
+                    index < G::POLICY_SIZE,
+                    "action index {index} is out of the policy of size {}",
+                    G::POLICY_SIZE
+                );
+                logits[index]
+            })
+            .collect::<Vec<_>>();
+        let max = priors.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        priors.iter_mut().for_each(|p| *p = (*p - max).exp());
+        let sum = priors.iter().sum::<f32>();
+        priors.iter_mut().for_each(|p| *p /= sum);
+
+        Evaluation {
+            value: (value * i16::MAX as f32) as i16,
+            priors: Some(priors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Subtraction;
+
+    fn layer(inputs: usize, outputs: usize) -> Layer {
+        let weights = (0..inputs * outputs)
+            .map(|i| i as f32 * 0.1 - 0.5)
+            .collect();
+        let biases = (0..outputs).map(|i| i as f32 * 0.01).collect();
+        Layer::new(inputs, outputs, weights, biases).unwrap()
+    }
+
+    fn network(inputs: usize, policy: usize) -> Network {
+        Network::new(
+            vec![layer(inputs, 4)],
+            vec![layer(4, policy)],
+            vec![layer(4, 2), layer(2, 1)],
+        )
+        .unwrap()
+    }
+
+    fn bytes(network: &Network) -> Vec<u8> {
+        let mut bytes = vec![];
+        network.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn rejects_invalid_layers() {
+        assert!(Layer::new(0, 0, vec![], vec![]).is_none());
+        assert!(Layer::new(2, 2, vec![0f32; 3], vec![0f32; 2]).is_none());
+        assert!(Network::new(vec![layer(2, 4)], vec![layer(3, 3)], vec![layer(4, 1)]).is_none());
+        assert!(Network::new(vec![], vec![layer(2, 3)], vec![layer(2, 2)]).is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        let network = network(2, 3);
+        let bytes = bytes(&network);
+        assert_eq!(Network::read(&mut bytes.as_slice()).unwrap(), network);
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        let mut bytes = bytes(&network(2, 3));
+        bytes[0] = b'X';
+        let error = Network::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let bytes = bytes(&network(2, 3));
+        for len in [6, 20, bytes.len() - 1] {
+            assert!(Network::read(&mut &bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_layer_sizes() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        // A trunk of one layer without inputs.
+        for x in [1u32, 0, 4] {
+            bytes.extend(x.to_le_bytes());
+        }
+        let error = Network::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // A layer too large to be allocated.
+        bytes.truncate(12);
+        for x in [u32::MAX, u32::MAX] {
+            bytes.extend(x.to_le_bytes());
+        }
+        let error = Network::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn evaluator_checks_dimensions() {
+        assert!(NeuralEvaluator::<Subtraction>::new(network(2, 3)).is_some());
+        assert!(NeuralEvaluator::<Subtraction>::new(network(3, 3)).is_none());
+        assert!(NeuralEvaluator::<Subtraction>::new(network(2, 2)).is_none());
+    }
+
+    #[test]
+    fn evaluator_masks_policy() {
+        let mut evaluator = NeuralEvaluator::new(network(2, 3)).unwrap();
+        let evaluation = evaluator.evaluate(&Subtraction::new(2));
+        let priors = evaluation.priors.unwrap();
+        assert_eq!(priors.len(), 2);
+        assert!((priors.iter().sum::<f32>() - 1f32).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,615 @@
+//! # Proof-number search
+//! Proof-number search (PNS) is a best-first search proving or disproving a
+//! [Goal] about the game-theoretic value of a state, such as "the side to move
+//! wins". Every node has a proof number and a disproof number: the minimum number
+//! of leaves to expand to respectively prove and disprove the goal from it. The
+//! search repeatedly expands a most-proving leaf, which contributes to both numbers
+//! of the root, until the root is solved or the node budget is exhausted.
+//!
+//! PN² keeps the tree small by expanding each leaf with a second-level search, of
+//! which only the children of the leaf are kept.
+//!
+//! Solved goals come with a [ProofTree]: how to answer every move of the opponent,
+//! down to terminal states. It can be [verified](Proof::verify) independently of
+//! the search, and exported as JSON.
+//!
+//! Only the exact utilities of terminal states count, states with an approximate
+//! utility being considered draws. Transpositions are not detected, so the search
+//! is best suited to small games and endgames.
+
+use rand::RngCore;
+use std::{
+    fmt::{Debug, Write as _},
+    io::{self, Write},
+};
+
+use crate::{
+    game::{ExactUtility, Game, Utility},
+    mcts::{escape_json, MonteCarloTree, SearchObserver},
+};
+
+// Proof and disproof numbers of solved nodes.
+const INFINITY: u32 = u32::MAX;
+
+/// Statement about the value of a state, proven or disproven by a
+/// [ProofNumberSearch].
+pub enum Goal<G: Game> {
+    /// The player wins.
+    Win(G::Player),
+    /// The player wins or draws.
+    NotLose(G::Player),
+}
+impl<G: Game> Clone for Goal<G> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<G: Game> Copy for Goal<G> {}
+impl<G: Game> Goal<G> {
+    /// The player the goal is about.
+    pub fn player(&self) -> G::Player {
+        match *self {
+            Goal::Win(player) | Goal::NotLose(player) => player,
+        }
+    }
+
+    /// Whether the goal holds in a state of the given utility, if it is known.
+    fn holds(&self, utility: Utility<G>) -> Option<bool> {
+        match (*self, utility) {
+            (_, Utility::Unknown) => None,
+            (_, Utility::Exact(ExactUtility::Win(p))) => Some(p == self.player()),
+            (Goal::Win(_), _) => Some(false),
+            (Goal::NotLose(_), _) => Some(true),
+        }
+    }
+}
+
+/// Outcome of a [ProofNumberSearch].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProofStatus {
+    Proven,
+    Disproven,
+    /// The node budget was exhausted before the goal was solved.
+    Unknown,
+}
+
+/// Moves proving (or disproving) a goal: a single move from states where the
+/// player the goal is about chooses, and every move from the other states, down to
+/// terminal states.
+pub struct ProofTree<G: Game> {
+    pub children: Vec<(G::Action, ProofTree<G>)>,
+}
+impl<G: Game> Clone for ProofTree<G> {
+    fn clone(&self) -> Self {
+        Self {
+            children: self.children.clone(),
+        }
+    }
+}
+impl<G: Game> ProofTree<G> {
+    /// Number of states of the tree.
+    pub fn size(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(|(_, child)| child.size())
+            .sum::<usize>()
+    }
+}
+
+/// Result of a [ProofNumberSearch].
+pub struct Proof<G: Game> {
+    pub goal: Goal<G>,
+    pub status: ProofStatus,
+    /// Number of nodes of the search tree.
+    pub nodes: usize,
+    /// Proof or disproof tree of the goal, if it was solved.
+    pub tree: Option<ProofTree<G>>,
+}
+impl<G: Game> Proof<G> {
+    /// Checks the proof tree by replaying it from the given state, which must be
+    /// the one the search started from. Unsolved goals never verify.
+    pub fn verify(&self, state: &mut G) -> bool {
+        match (&self.tree, self.status) {
+            (Some(tree), ProofStatus::Proven) => verify(tree, state, &self.goal, true),
+            (Some(tree), ProofStatus::Disproven) => verify(tree, state, &self.goal, false),
+            _ => false,
+        }
+    }
+
+    /// Writes the proof tree as JSON, each state being an object with the
+    /// `action` leading to it (formatted using its [Debug] impl) and its nested
+    /// `children`. Unsolved goals are written as `null`.
+    pub fn write_json(&self, mut writer: impl Write) -> io::Result<()>
+    where
+        G::Action: Debug,
+    {
+        match &self.tree {
+            Some(tree) => {
+                let mut json = String::new();
+                write_json_node(&mut json, None, tree);
+                writeln!(writer, "{json}")
+            }
+            None => writeln!(writer, "null"),
+        }
+    }
+}
+
+/// Parameters of a [ProofNumberSearch].
+#[derive(Clone, Debug)]
+pub struct PnsConfig {
+    /// Maximum number of nodes of the search tree, after which the search gives
+    /// up. The last expansion may exceed it by the number of actions of a state.
+    pub max_nodes: usize,
+    /// Enables PN²: leaves are expanded by a second-level search, whose budget is
+    /// the given share of the number of nodes of the first-level tree.
+    pub pn2: Option<f32>,
+}
+impl Default for PnsConfig {
+    fn default() -> Self {
+        Self {
+            max_nodes: 1 << 20,
+            pn2: None,
+        }
+    }
+}
+
+// A node of the search tree.
+struct PnsNode<G: Game> {
+    action: Option<G::Action>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    expanded: bool,
+    // Whether the player of the goal chooses the move in this node.
+    or_node: bool,
+    proof: u32,
+    disproof: u32,
+    // Proof tree of nodes solved by a second-level search, whose subtree is not kept.
+    solved: Option<ProofTree<G>>,
+}
+
+/// A proof-number search solver, parametrized by the game it is solving.
+pub struct ProofNumberSearch<G: Game> {
+    config: PnsConfig,
+    nodes: Vec<PnsNode<G>>,
+    // A winner other than the player of the goal, met in a terminal state.
+    opponent: Option<G::Player>,
+}
+impl<G: Game> Default for ProofNumberSearch<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<G: Game> ProofNumberSearch<G> {
+    /// Constructs a solver using the default parameters.
+    pub fn new() -> Self {
+        Self::with_config(PnsConfig::default())
+    }
+
+    /// Constructs a solver using the given parameters.
+    pub fn with_config(config: PnsConfig) -> Self {
+        Self {
+            config,
+            nodes: vec![],
+            opponent: None,
+        }
+    }
+
+    /// Returns the parameters of the solver.
+    pub fn config(&self) -> &PnsConfig {
+        &self.config
+    }
+
+    /// Gives mutable access to the parameters of the solver.
+    pub fn config_mut(&mut self) -> &mut PnsConfig {
+        &mut self.config
+    }
+
+    /// Proves or disproves a goal from the given state, within the node budget.
+    pub fn prove(&mut self, state: &mut G, goal: Goal<G>) -> Proof<G> {
+        self.nodes.clear();
+        self.opponent = None;
+        let root = self.new_node(state, None, None, &goal);
+        self.nodes.push(root);
+
+        while self.nodes[0].proof != 0
+            && self.nodes[0].disproof != 0
+            && self.nodes.len() < self.config.max_nodes
+        {
+            // Selection of a most-proving node.
+            let mut node = 0;
+            let mut depth = 0;
+            while self.nodes[node].expanded {
+                let children = &self.nodes[node].children;
+                node = if self.nodes[node].or_node {
+                    *children
+                        .iter()
+                        .min_by_key(|&&c| self.nodes[c].proof)
+                        .unwrap()
+                } else {
+                    *children
+                        .iter()
+                        .min_by_key(|&&c| self.nodes[c].disproof)
+                        .unwrap()
+                };
+                state.play(self.nodes[node].action.as_ref().unwrap());
+                depth += 1;
+            }
+
+            self.expand(state, node, &goal);
+            self.update(node);
+            for _ in 0..depth {
+                state.undo();
+            }
+        }
+
+        let status = match (self.nodes[0].proof, self.nodes[0].disproof) {
+            (0, _) => ProofStatus::Proven,
+            (_, 0) => ProofStatus::Disproven,
+            _ => ProofStatus::Unknown,
+        };
+        Proof {
+            goal,
+            status,
+            nodes: self.nodes.len(),
+            tree: (status != ProofStatus::Unknown).then(|| self.tree(0)),
+        }
+    }
+
+    /// Solves the given state, returning its exact utility if it is found within the
+    /// node budget. The side to move is first proven to win, then not to lose, so
+    /// this is meant for two-player games.
+    pub fn solve(&mut self, state: &mut G) -> Option<ExactUtility<G>> {
+        let player = state.current_player();
+        match self.prove(state, Goal::Win(player)).status {
+            ProofStatus::Proven => return Some(ExactUtility::Win(player)),
+            ProofStatus::Disproven => {}
+            ProofStatus::Unknown => return None,
+        }
+        match self.prove(state, Goal::NotLose(player)).status {
+            ProofStatus::Proven => Some(ExactUtility::Draw),
+            ProofStatus::Disproven => self.opponent.map(ExactUtility::Win),
+            ProofStatus::Unknown => None,
+        }
+    }
+
+    /// Checks the values proven by a [MonteCarloTree] against this solver, for the
+    /// given state and the states of the tree following it, up to `max_depth` plies.
+    /// Returns the actions leading to the first state whose proven value differs
+    /// from the solver's, if any. States the solver cannot solve within its budget
+    /// are not checked.
+    pub fn check_solver<O: SearchObserver<G>, R: RngCore>(
+        &mut self,
+        tree: &MonteCarloTree<G, O, R>,
+        state: &mut G,
+        max_depth: usize,
+    ) -> Option<Vec<G::Action>> {
+        if let Some(proven) = tree.proven_utility(state) {
+            let agrees = |solved| match (solved, proven) {
+                (ExactUtility::Win(p), ExactUtility::Win(q)) => p == q,
+                (ExactUtility::Draw, ExactUtility::Draw) => true,
+                _ => false,
+            };
+            if self.solve(state).is_some_and(|solved| !agrees(solved)) {
+                return Some(vec![]);
+            }
+        }
+        if max_depth == 0 {
+            return None;
+        }
+
+        for (action, visits) in tree.visit_counts(state) {
+            if visits == 0 {
+                continue;
+            }
+            state.play(&action);
+            let mismatch = self.check_solver(tree, state, max_depth - 1);
+            state.undo();
+            if let Some(mut actions) = mismatch {
+                actions.insert(0, action);
+                return Some(actions);
+            }
+        }
+        None
+    }
+
+    /// Creates an unexpanded node for the given state.
+    fn new_node(
+        &mut self,
+        state: &G,
+        action: Option<G::Action>,
+        parent: Option<usize>,
+        goal: &Goal<G>,
+    ) -> PnsNode<G> {
+        let utility = state.utility();
+        if let Utility::Exact(ExactUtility::Win(p)) = utility {
+            if p != goal.player() {
+                self.opponent = Some(p);
+            }
+        }
+        let (proof, disproof) = match goal.holds(utility) {
+            Some(true) => (0, INFINITY),
+            Some(false) => (INFINITY, 0),
+            None => (1, 1),
+        };
+        PnsNode {
+            action,
+            parent,
+            children: vec![],
+            expanded: false,
+            or_node: state.current_player() == goal.player(),
+            proof,
+            disproof,
+            solved: None,
+        }
+    }
+
+    /// Creates the children of a leaf, with their proof and disproof numbers
+    /// given by a second-level search with PN².
+    fn expand(&mut self, state: &mut G, node: usize, goal: &Goal<G>) {
+        self.nodes[node].expanded = true;
+        if state.actions().into_iter().next().is_none() {
+            // States without actions nor a known utility are draws.
+            let holds = goal.holds(Utility::Exact(ExactUtility::Draw)) == Some(true);
+            let node = &mut self.nodes[node];
+            (node.proof, node.disproof) = if holds { (0, INFINITY) } else { (INFINITY, 0) };
+            return;
+        }
+        let Some(share) = self.config.pn2 else {
+            for action in state.actions() {
+                state.play(&action);
+                let child = self.new_node(state, Some(action), Some(node), goal);
+                state.undo();
+                self.push_child(node, child);
+            }
+            return;
+        };
+
+        let budget = ((share * self.nodes.len() as f32) as usize).max(2);
+        let mut second = ProofNumberSearch::with_config(PnsConfig {
+            max_nodes: budget,
+            pn2: None,
+        });
+        second.prove(state, *goal);
+        self.opponent = self.opponent.or(second.opponent);
+        for i in second.nodes[0].children.clone() {
+            let child = &second.nodes[i];
+            let solved = child.proof == 0 || child.disproof == 0;
+            let child = PnsNode {
+                action: child.action.clone(),
+                parent: Some(node),
+                children: vec![],
+                expanded: false,
+                or_node: child.or_node,
+                proof: child.proof,
+                disproof: child.disproof,
+                solved: solved.then(|| second.tree(i)),
+            };
+            self.push_child(node, child);
+        }
+    }
+
+    fn push_child(&mut self, node: usize, child: PnsNode<G>) {
+        self.nodes.push(child);
+        let child = self.nodes.len() - 1;
+        self.nodes[node].children.push(child);
+    }
+
+    /// Updates the proof and disproof numbers of a node and of its ancestors.
+    fn update(&mut self, mut node: usize) {
+        loop {
+            // Nodes without children keep the numbers given on expansion.
+            let children = &self.nodes[node].children;
+            if !children.is_empty() {
+                let proofs = children.iter().map(|&c| self.nodes[c].proof);
+                let disproofs = children.iter().map(|&c| self.nodes[c].disproof);
+                let (proof, disproof) = if self.nodes[node].or_node {
+                    (
+                        proofs.min().unwrap(),
+                        disproofs.fold(0, u32::saturating_add),
+                    )
+                } else {
+                    (
+                        proofs.fold(0, u32::saturating_add),
+                        disproofs.min().unwrap(),
+                    )
+                };
+                self.nodes[node].proof = proof;
+                self.nodes[node].disproof = disproof;
+            }
+
+            match self.nodes[node].parent {
+                Some(parent) => node = parent,
+                None => return,
+            }
+        }
+    }
+
+    /// Extracts the proof or disproof tree of a solved node.
+    fn tree(&self, node: usize) -> ProofTree<G> {
+        let node = &self.nodes[node];
+        if let Some(tree) = &node.solved {
+            return tree.clone();
+        }
+        let proven = node.proof == 0;
+        let solved = |c: &usize| {
+            if proven {
+                self.nodes[*c].proof == 0
+            } else {
+                self.nodes[*c].disproof == 0
+            }
+        };
+
+        let children = if node.or_node == proven {
+            // A single move is enough.
+            node.children
+                .iter()
+                .find(|c| solved(c))
+                .into_iter()
+                .collect()
+        } else {
+            node.children.iter().collect::<Vec<_>>()
+        };
+        ProofTree {
+            children: children
+                .into_iter()
+                .map(|&c| (self.nodes[c].action.clone().unwrap(), self.tree(c)))
+                .collect(),
+        }
+    }
+}
+
+/// Checks a proof tree of a goal, or a disproof tree if `proven` is false.
+fn verify<G: Game>(tree: &ProofTree<G>, state: &mut G, goal: &Goal<G>, proven: bool) -> bool {
+    let actions = state.actions().into_iter().collect::<Vec<_>>();
+    let utility = match state.utility() {
+        Utility::Unknown if actions.is_empty() => Utility::Exact(ExactUtility::Draw),
+        utility => utility,
+    };
+    if let Some(holds) = goal.holds(utility) {
+        return holds == proven;
+    }
+
+    let chooses = (state.current_player() == goal.player()) == proven;
+    let mut check = |action: &G::Action, child: &ProofTree<G>| {
+        state.play(action);
+        let valid = verify(child, state, goal, proven);
+        state.undo();
+        valid
+    };
+    if chooses {
+        tree.children
+            .first()
+            .is_some_and(|(action, child)| actions.contains(action) && check(action, child))
+    } else {
+        actions.iter().all(|action| {
+            tree.children
+                .iter()
+                .find(|(a, _)| a == action)
+                .is_some_and(|(_, child)| check(action, child))
+        })
+    }
+}
+
+fn write_json_node<G: Game>(json: &mut String, action: Option<&G::Action>, tree: &ProofTree<G>)
+where
+    G::Action: Debug,
+{
+    json.push('{');
+    if let Some(action) = action {
+        write!(
+            json,
+            "\"action\":\"{}\",",
+            escape_json(&format!("{action:?}"))
+        )
+        .unwrap();
+    }
+    json.push_str("\"children\":[");
+    for (i, (action, child)) in tree.children.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write_json_node(json, Some(action), child);
+    }
+    json.push_str("]}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Subtraction, MAX_TAKE};
+
+    fn wins(pile: u32) -> bool {
+        !pile.is_multiple_of(MAX_TAKE + 1)
+    }
+
+    #[test]
+    fn proves_and_disproves_wins() {
+        let mut pns = ProofNumberSearch::new();
+        for pile in 1..=12 {
+            let mut state = Subtraction::new(pile);
+            let proof = pns.prove(&mut state, Goal::Win(0));
+            let expected = if wins(pile) {
+                ProofStatus::Proven
+            } else {
+                ProofStatus::Disproven
+            };
+            assert_eq!(proof.status, expected, "pile {pile}");
+            assert!(proof.verify(&mut state), "pile {pile}");
+            assert_eq!(state.pile, pile);
+        }
+    }
+
+    #[test]
+    fn proof_tree_plays_winning_moves() {
+        let mut state = Subtraction::new(7);
+        let proof = ProofNumberSearch::new().prove(&mut state, Goal::Win(0));
+        let tree = proof.tree.unwrap();
+        assert_eq!(tree.children.len(), 1);
+        assert_eq!(tree.children[0].0, 3);
+        // Every answer of the opponent is covered.
+        assert_eq!(tree.children[0].1.children.len(), MAX_TAKE as usize);
+    }
+
+    #[test]
+    fn rejects_invalid_proofs() {
+        let mut state = Subtraction::new(6);
+        let mut proof = ProofNumberSearch::new().prove(&mut state, Goal::Win(0));
+        assert!(proof.verify(&mut state));
+
+        // A losing first move.
+        let tree = proof.tree.as_mut().unwrap();
+        tree.children[0].0 = 1;
+        assert!(!proof.verify(&mut state));
+
+        // A disproof missing an answer of the player.
+        let mut state = Subtraction::new(8);
+        let mut proof = ProofNumberSearch::new().prove(&mut state, Goal::Win(0));
+        assert!(proof.verify(&mut state));
+        proof.tree.as_mut().unwrap().children.pop();
+        assert!(!proof.verify(&mut state));
+
+        // The proof of another state.
+        assert!(!proof.verify(&mut Subtraction::new(9)));
+    }
+
+    #[test]
+    fn unsolved_goals_do_not_verify() {
+        let config = PnsConfig {
+            max_nodes: 2,
+            pn2: None,
+        };
+        let mut state = Subtraction::new(30);
+        let proof = ProofNumberSearch::with_config(config).prove(&mut state, Goal::NotLose(0));
+        assert_eq!(proof.status, ProofStatus::Unknown);
+        assert!(proof.tree.is_none());
+        assert!(!proof.verify(&mut state));
+    }
+
+    #[test]
+    fn solves_with_pn2() {
+        let config = PnsConfig {
+            pn2: Some(1f32),
+            ..Default::default()
+        };
+        let mut pns = ProofNumberSearch::with_config(config);
+        for pile in 1..=12 {
+            let mut state = Subtraction::new(pile);
+            let winner = if wins(pile) { 0 } else { 1 };
+            assert!(matches!(pns.solve(&mut state), Some(ExactUtility::Win(p)) if p == winner));
+            let proof = pns.prove(&mut state, Goal::NotLose(0));
+            assert!(proof.verify(&mut state), "pile {pile}");
+        }
+    }
+
+    #[test]
+    fn agrees_with_the_tree_solver() {
+        let mut state = Subtraction::new(10);
+        let mut tree = MonteCarloTree::new();
+        for _ in 0..2000 {
+            tree.step(&mut state);
+        }
+        let mut pns = ProofNumberSearch::new();
+        assert!(pns.check_solver(&tree, &mut state, 10).is_none());
+    }
+}
@@ -0,0 +1,404 @@
+//! # Self-play
+//! Generation of training data for evaluators by having a [MonteCarloTree] play
+//! against itself.
+//!
+//! For every position reached during a game, a [Sample] records the encoded state,
+//! the distribution of visits among the root's children at the end of the search
+//! and the final outcome of the game. Moves are sampled from the visit distribution
+//! (sharpened or flattened by a temperature) so that games do not all look alike,
+//! and Dirichlet noise can be added to the root priors to further diversify them.
+//!
+//! ## File format
+//! Samples are written in a simple little-endian binary format:
+//! - the magic bytes `CHSP` followed by the format version as a `u32` (currently 1).
+//! - the size of feature tensors and of policies, as `u32`s.
+//! - the samples themselves, each made of its features, its policy and its outcome
+//!   as `f32`s. The number of samples follows from the size of the file.
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, SeedableRng};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    binary::{read_header, read_u32, write_header},
+    evaluator::TensorEncoding,
+    game::{ExactUtility, Game, Utility},
+    mcts::{DirichletNoise, MonteCarloTree, SearchConfig},
+};
+
+const MAGIC: &[u8; 4] = b"CHSP";
+const VERSION: u32 = 1;
+
+/// Parameters of self-play games.
+#[derive(Clone, Debug)]
+pub struct SelfPlayConfig {
+    /// Number of calls to [MonteCarloTree::step] before each move.
+    pub steps_per_move: u32,
+    /// Temperature used to sample moves from the root visit distribution. A
+    /// temperature of 0 always plays the most visited move.
+    pub temperature: f32,
+    /// Number of plies after which moves are always the most visited ones.
+    pub temperature_plies: u32,
+    /// Noise added to the root priors of every search, overriding the one of the
+    /// tree's configuration.
+    pub root_noise: Option<DirichletNoise>,
+    /// Maximum number of plies of a game, after which it is scored as a draw.
+    pub max_plies: Option<u32>,
+    /// Seed of the sampling of moves, making games reproducible. Each game's tree
+    /// is then also seeded with a stream derived from it, see [SearchConfig::stream].
+    pub seed: Option<u64>,
+}
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        Self {
+            steps_per_move: 800,
+            temperature: 1f32,
+            temperature_plies: 30,
+            root_noise: Some(DirichletNoise {
+                alpha: 0.3,
+                epsilon: 0.25,
+            }),
+            max_plies: None,
+            seed: None,
+        }
+    }
+}
+
+/// A training sample recorded during self-play.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// The state, as encoded by [TensorEncoding::to_tensor].
+    pub features: Vec<f32>,
+    /// Share of the root visits given to each action, indexed by
+    /// [TensorEncoding::action_index].
+    pub policy: Vec<f32>,
+    /// Final result of the game for the side to move, between -1 and 1.
+    pub outcome: f32,
+}
+
+/// Self-play driver, creating a new search tree for each game.
+pub struct SelfPlay<G: Game, F: FnMut() -> MonteCarloTree<G>> {
+    config: SelfPlayConfig,
+    new_tree: F,
+    rng: StdRng,
+    // Number of games played so far, used to derive the seed of each tree.
+    games: u64,
+}
+impl<G: TensorEncoding, F: FnMut() -> MonteCarloTree<G>> SelfPlay<G, F> {
+    /// Creates a self-play driver, using `new_tree` to create the tree used for each
+    /// game.
+    pub fn new(config: SelfPlayConfig, new_tree: F) -> Self {
+        Self {
+            rng: config
+                .seed
+                .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            config,
+            new_tree,
+            games: 0,
+        }
+    }
+
+    /// Plays a game from the given state until it ends, returning the samples
+    /// recorded along the way.
+    pub fn play_game(&mut self, mut state: G) -> Vec<Sample> {
+        let mut tree = (self.new_tree)();
+        if self.config.root_noise.is_some() {
+            tree.config_mut().root_noise = self.config.root_noise;
+        }
+        if let Some(seed) = self.config.seed {
+            let config = SearchConfig {
+                seed: Some(seed),
+                ..tree.config().clone()
+            };
+            tree = tree.with_rng(config.stream(self.games).rng());
+        }
+        self.games += 1;
+
+        // Samples along with the player they were recorded for, the outcome is
+        // filled once the game is over.
+        let mut samples = vec![];
+        let mut plies = 0;
+        let result = loop {
+            match state.utility() {
+                Utility::Unknown => {}
+                utility => break Some(utility),
+            }
+            if self.config.max_plies.is_some_and(|max| plies >= max) {
+                break None;
+            }
+
+            for _ in 0..self.config.steps_per_move {
+                tree.step(&mut state);
+            }
+
+            // Record the root visit distribution.
+            let visits = tree.visit_counts(&mut state);
+            let total = visits.iter().map(|(_, v)| *v).sum::<u32>().max(1) as f32;
+            let mut policy = vec![0f32; G::POLICY_SIZE];
+            for (action, v) in &visits {
+                policy[state.action_index(action)] = *v as f32 / total;
+            }
+            samples.push((
+                state.current_player(),
+                Sample {
+                    features: state.to_tensor(),
+                    policy,
+                    outcome: 0f32,
+                },
+            ));
+
+            // Then sample the move to play.
+            let temperature = if plies < self.config.temperature_plies {
+                self.config.temperature
+            } else {
+                0f32
+            };
+            let action = if temperature > 0f32 {
+                let weights = visits
+                    .iter()
+                    .map(|(_, v)| (*v as f32).powf(1f32 / temperature))
+                    .collect::<Vec<_>>();
+                match WeightedIndex::new(&weights) {
+                    Ok(distribution) => visits.into_iter().nth(distribution.sample(&mut self.rng)),
+                    Err(_) => visits.into_iter().max_by_key(|(_, v)| *v),
+                }
+            } else {
+                visits.into_iter().max_by_key(|(_, v)| *v)
+            }
+            .map(|(action, _)| action)
+            .expect("non-terminal states should have legal actions");
+
+            state.play(&action);
+            plies += 1;
+        };
+
+        // Fill in the outcome of the game for every sample.
+        let final_player = state.current_player();
+        samples
+            .into_iter()
+            .map(|(player, mut sample)| {
+                sample.outcome = match result {
+                    Some(Utility::Exact(ExactUtility::Win(p))) => {
+                        if p == player {
+                            1f32
+                        } else {
+                            -1f32
+                        }
+                    }
+                    Some(Utility::Approximate(approx)) => {
+                        let approx = approx as f32 / i16::MAX as f32;
+                        if player == final_player {
+                            approx
+                        } else {
+                            -approx
+                        }
+                    }
+                    _ => 0f32,
+                };
+                sample
+            })
+            .collect()
+    }
+
+    /// Plays a number of games, starting from states created by `new_game`, and
+    /// writes their samples, flushing the writer at the end. Returns the number of
+    /// samples written.
+    pub fn generate<W: Write>(
+        &mut self,
+        games: usize,
+        mut new_game: impl FnMut() -> G,
+        writer: &mut SampleWriter<W>,
+    ) -> io::Result<usize> {
+        let mut written = 0;
+        for _ in 0..games {
+            for sample in self.play_game(new_game()) {
+                writer.write(&sample)?;
+                written += 1;
+            }
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+}
+
+/// Writes samples in the format described in the [module documentation](self).
+pub struct SampleWriter<W: Write> {
+    writer: W,
+    features: usize,
+    policy: usize,
+}
+impl SampleWriter<BufWriter<File>> {
+    /// Creates a samples file for the given game.
+    pub fn create<G: TensorEncoding>(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new::<G>(BufWriter::new(File::create(path)?))
+    }
+}
+impl<W: Write> SampleWriter<W> {
+    /// Writes the header of a samples file for the given game.
+    pub fn new<G: TensorEncoding>(mut writer: W) -> io::Result<Self> {
+        write_header(&mut writer, MAGIC, VERSION)?;
+        writer.write_all(&(G::TENSOR_SIZE as u32).to_le_bytes())?;
+        writer.write_all(&(G::POLICY_SIZE as u32).to_le_bytes())?;
+        Ok(Self {
+            writer,
+            features: G::TENSOR_SIZE,
+            policy: G::POLICY_SIZE,
+        })
+    }
+
+    /// Appends a sample to the file.
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        if sample.features.len() != self.features || sample.policy.len() != self.policy {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sample dimensions do not match the file",
+            ));
+        }
+        for x in sample
+            .features
+            .iter()
+            .chain(&sample.policy)
+            .chain([&sample.outcome])
+        {
+            self.writer.write_all(&x.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads all samples from a file in the format described in the
+/// [module documentation](self).
+pub fn read_samples(path: impl AsRef<Path>) -> io::Result<Vec<Sample>> {
+    read_samples_from(&mut BufReader::new(File::open(path)?))
+}
+
+/// Reads all samples in the format described in the [module documentation](self),
+/// until the end of the reader.
+pub fn read_samples_from(reader: &mut impl Read) -> io::Result<Vec<Sample>> {
+    read_header(reader, MAGIC, VERSION, "self-play samples")?;
+    let features = read_u32(reader)? as usize;
+    let policy = read_u32(reader)? as usize;
+
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let sample_size = (features + policy + 1) * 4;
+    if bytes.len() % sample_size != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated self-play sample",
+        ));
+    }
+    let values = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect::<Vec<_>>();
+    Ok(values
+        .chunks_exact(features + policy + 1)
+        .map(|sample| Sample {
+            features: sample[..features].to_vec(),
+            policy: sample[features..features + policy].to_vec(),
+            outcome: sample[features + policy],
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Subtraction;
+
+    fn samples() -> Vec<Sample> {
+        let config = SelfPlayConfig {
+            steps_per_move: 50,
+            seed: Some(0),
+            ..Default::default()
+        };
+        SelfPlay::new(config, MonteCarloTree::new).play_game(Subtraction::new(6))
+    }
+
+    fn write_samples(samples: &[Sample]) -> Vec<u8> {
+        let mut writer = SampleWriter::new::<Subtraction>(vec![]).unwrap();
+        for sample in samples {
+            writer.write(sample).unwrap();
+        }
+        writer.writer
+    }
+
+    #[test]
+    fn play_game_records_outcomes() {
+        let samples = samples();
+        assert!(!samples.is_empty());
+        for (i, sample) in samples.iter().enumerate() {
+            assert_eq!(sample.features.len(), Subtraction::TENSOR_SIZE);
+            assert!((sample.policy.iter().sum::<f32>() - 1f32).abs() < 1e-4);
+            // The last sample is the winning move, and results alternate before it.
+            let expected = if (samples.len() - 1 - i).is_multiple_of(2) {
+                1f32
+            } else {
+                -1f32
+            };
+            assert_eq!(sample.outcome, expected);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let samples = samples();
+        let bytes = write_samples(&samples);
+        assert_eq!(read_samples_from(&mut bytes.as_slice()).unwrap(), samples);
+    }
+
+    #[test]
+    fn generate_flushes_samples_to_the_file() {
+        let path = std::env::temp_dir().join(format!("chameleon-{}.samples", std::process::id()));
+        let mut writer = SampleWriter::create::<Subtraction>(&path).unwrap();
+        let config = SelfPlayConfig {
+            steps_per_move: 50,
+            seed: Some(0),
+            ..Default::default()
+        };
+        let written = SelfPlay::new(config, MonteCarloTree::new)
+            .generate(2, || Subtraction::new(6), &mut writer)
+            .unwrap();
+
+        // The writer is still alive, so the samples must have been flushed already.
+        let samples = read_samples(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples.unwrap().len(), written);
+    }
+
+    #[test]
+    fn rejects_mismatched_samples() {
+        let mut writer = SampleWriter::new::<Subtraction>(vec![]).unwrap();
+        let sample = Sample {
+            features: vec![0f32],
+            policy: vec![0f32; Subtraction::POLICY_SIZE],
+            outcome: 0f32,
+        };
+        assert!(writer.write(&sample).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        let mut bytes = write_samples(&samples());
+        bytes[0] = b'X';
+        let error = read_samples_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let bytes = write_samples(&samples());
+        for len in [10, bytes.len() - 1] {
+            assert!(read_samples_from(&mut &bytes[..len]).is_err());
+        }
+    }
+}
@@ -0,0 +1,408 @@
+//! # Endgame tablebases
+//! For games whose states can all be enumerated, retrograde analysis computes the
+//! exact value of every state: starting from the terminal states, values are
+//! propagated backwards to the predecessors of solved states. A state with an
+//! action leading to a lost state for the opponent is won, a state whose actions
+//! all lead to won states for the opponent is lost, and the states left once
+//! nothing more can be solved are draws.
+//!
+//! A [Tablebase] keeps the value of each state for its side to move, along with its
+//! distance to the end of the game when played perfectly: winners take the fastest
+//! wins and losers the slowest losses. It can be probed directly, or given to a
+//! [MonteCarloTree](crate::mcts::MonteCarloTree) as an [Oracle].
+//!
+//! Retrograde analysis is meant for two-player games, whose states must implement
+//! [Enumerable].
+//!
+//! ## File format
+//! Tablebases are stored in a compact little-endian binary format:
+//! - the magic bytes `CHTB` followed by the format version as a `u32` (currently 1).
+//! - the number of states as a `u64`.
+//! - an entry per state as a `u16`, in the order of [Enumerable::states]: the
+//!   outcome in the two high bits (0 for a draw, 1 for a win and 2 for a loss) and
+//!   the distance in the others, saturating at 16383 plies.
+//!
+//! States are not stored, so reading a table enumerates them again.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    binary::{invalid_data, read_header, read_u64, write_header},
+    evaluator::Oracle,
+    game::{ExactUtility, Game, Utility},
+};
+
+const MAGIC: &[u8; 4] = b"CHTB";
+const VERSION: u32 = 1;
+// Largest distance stored in files.
+const MAX_DISTANCE: u16 = (1 << 14) - 1;
+
+/// Games whose state space can be enumerated, and searched backwards.
+pub trait Enumerable: Game {
+    type StatesIter: IntoIterator<Item = Self>;
+    type PredecessorsIter: IntoIterator<Item = Self>;
+
+    /// Every state of the game, always in the same order. The set of states must be
+    /// closed under [Game::play]: states whose successors are missing cannot be
+    /// solved and end up as draws.
+    fn states() -> Self::StatesIter;
+    /// The states from which an action leads to this one.
+    fn predecessors(&self) -> Self::PredecessorsIter;
+}
+
+/// Value of a state for its side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+/// Entry of a [Tablebase].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TableEntry {
+    pub outcome: Outcome,
+    /// Number of plies until the end of the game under perfect play, 0 for draws.
+    pub distance: u16,
+}
+impl TableEntry {
+    fn encode(self) -> u16 {
+        let outcome = match self.outcome {
+            Outcome::Draw => 0,
+            Outcome::Win => 1,
+            Outcome::Loss => 2,
+        };
+        outcome << 14 | self.distance.min(MAX_DISTANCE)
+    }
+
+    fn decode(bits: u16) -> Option<Self> {
+        let outcome = match bits >> 14 {
+            0 => Outcome::Draw,
+            1 => Outcome::Win,
+            2 => Outcome::Loss,
+            _ => return None,
+        };
+        Some(Self {
+            outcome,
+            distance: bits & MAX_DISTANCE,
+        })
+    }
+}
+
+/// The exact value of every state of a game, keyed by [Game::hash].
+pub struct Tablebase<G: Game> {
+    entries: HashMap<G::Hash, TableEntry>,
+}
+impl<G: Enumerable> Tablebase<G> {
+    /// Solves every state given by [Enumerable::states] by retrograde analysis.
+    pub fn build() -> Self {
+        let mut states = G::states().into_iter().collect::<Vec<_>>();
+        let index = states
+            .iter()
+            .enumerate()
+            .map(|(i, state)| (state.hash(), i))
+            .collect::<HashMap<_, _>>();
+        let mut entries = vec![None; states.len()];
+        // Number of distinct successors of each state not yet known to be won for
+        // the opponent.
+        let mut remaining = vec![0usize; states.len()];
+        let mut solved = VecDeque::new();
+
+        for (i, state) in states.iter_mut().enumerate() {
+            let outcome = match state.utility() {
+                Utility::Exact(ExactUtility::Win(p)) if p == state.current_player() => Outcome::Win,
+                Utility::Exact(ExactUtility::Win(_)) => Outcome::Loss,
+                Utility::Exact(ExactUtility::Draw) | Utility::Approximate(_) => Outcome::Draw,
+                Utility::Unknown => {
+                    let mut successors = HashSet::new();
+                    for action in state.actions() {
+                        state.play(&action);
+                        successors.insert(state.hash());
+                        state.undo();
+                    }
+                    remaining[i] = successors.len();
+                    continue;
+                }
+            };
+            entries[i] = Some(TableEntry {
+                outcome,
+                distance: 0,
+            });
+            if outcome != Outcome::Draw {
+                solved.push_back(i);
+            }
+        }
+
+        // States are solved in order of distance, so that the first won successor
+        // found gives the fastest win, and the last lost one the slowest loss.
+        while let Some(i) = solved.pop_front() {
+            let entry = entries[i].unwrap();
+            let player = states[i].current_player();
+            let mut seen = HashSet::new();
+            for predecessor in states[i].predecessors() {
+                let Some(&j) = index.get(&predecessor.hash()) else {
+                    continue;
+                };
+                if entries[j].is_some() || !seen.insert(j) {
+                    continue;
+                }
+
+                let same_player = states[j].current_player() == player;
+                let outcome = if (entry.outcome == Outcome::Win) == same_player {
+                    Outcome::Win
+                } else {
+                    remaining[j] -= 1;
+                    if remaining[j] > 0 {
+                        continue;
+                    }
+                    Outcome::Loss
+                };
+                entries[j] = Some(TableEntry {
+                    outcome,
+                    distance: entry.distance.saturating_add(1),
+                });
+                solved.push_back(j);
+            }
+        }
+
+        let entries = states
+            .iter()
+            .zip(entries)
+            .map(|(state, entry)| {
+                let entry = entry.unwrap_or(TableEntry {
+                    outcome: Outcome::Draw,
+                    distance: 0,
+                });
+                (state.hash(), entry)
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Loads a tablebase from a file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Saves the tablebase to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Reads a tablebase in the format described in the [module documentation](self).
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        read_header(reader, MAGIC, VERSION, "tablebase")?;
+        let count = read_u64(reader)?;
+
+        let mut entries = HashMap::new();
+        let mut bytes = [0u8; 2];
+        for state in G::states() {
+            reader.read_exact(&mut bytes)?;
+            let entry = TableEntry::decode(u16::from_le_bytes(bytes))
+                .ok_or_else(|| invalid_data("invalid tablebase entry"))?;
+            entries.insert(state.hash(), entry);
+        }
+        if entries.len() as u64 != count {
+            return Err(invalid_data("mismatched number of states"));
+        }
+        Ok(Self { entries })
+    }
+
+    /// Writes the tablebase in the format described in the
+    /// [module documentation](self).
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_header(writer, MAGIC, VERSION)?;
+        writer.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for state in G::states() {
+            let entry = self
+                .entries
+                .get(&state.hash())
+                .ok_or_else(|| invalid_data("state missing from the tablebase"))?;
+            writer.write_all(&entry.encode().to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+impl<G: Game> Tablebase<G> {
+    /// Number of states of the tablebase.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the tablebase has no state.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entry of the given state, if it is in the tablebase.
+    pub fn get(&self, state: &G) -> Option<TableEntry> {
+        self.entries.get(&state.hash()).copied()
+    }
+
+    /// Returns the exact utility of the given state, if it is in the tablebase. The
+    /// winner of lost states is found by following the game to its end.
+    pub fn utility(&self, state: &mut G) -> Option<ExactUtility<G>> {
+        let entry = self.get(state)?;
+        match entry.outcome {
+            Outcome::Win => Some(ExactUtility::Win(state.current_player())),
+            Outcome::Draw => Some(ExactUtility::Draw),
+            Outcome::Loss if entry.distance == 0 => match state.utility() {
+                Utility::Exact(utility) => Some(utility),
+                _ => None,
+            },
+            Outcome::Loss => {
+                let action = self.best_action(state)?;
+                state.play(&action);
+                let utility = self.utility(state);
+                state.undo();
+                utility
+            }
+        }
+    }
+
+    /// Returns the best action of the given state: the fastest win, a draw, or the
+    /// slowest loss. Returns [None] if the state has no action in the tablebase.
+    pub fn best_action(&self, state: &mut G) -> Option<G::Action> {
+        let player = state.current_player();
+        let mut best = None;
+        for action in state.actions() {
+            state.play(&action);
+            let score = self.get(state).map(|entry| {
+                let outcome = match entry.outcome {
+                    outcome if state.current_player() == player => outcome,
+                    Outcome::Win => Outcome::Loss,
+                    Outcome::Loss => Outcome::Win,
+                    Outcome::Draw => Outcome::Draw,
+                };
+                match outcome {
+                    Outcome::Win => (2, -(entry.distance as i32)),
+                    Outcome::Draw => (1, 0),
+                    Outcome::Loss => (0, entry.distance as i32),
+                }
+            });
+            state.undo();
+            if let Some(score) = score.filter(|score| best.as_ref().is_none_or(|(s, _)| score > s))
+            {
+                best = Some((score, action));
+            }
+        }
+        best.map(|(_, action)| action)
+    }
+}
+
+impl<G: Game> Oracle<G> for Tablebase<G> {
+    fn probe(&self, state: &mut G) -> Option<(ExactUtility<G>, u32)> {
+        let distance = self.get(state)?.distance as u32;
+        Some((self.utility(state)?, distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Subtraction, MAX_PILE, MAX_TAKE};
+
+    fn bytes(tablebase: &Tablebase<Subtraction>) -> Vec<u8> {
+        let mut bytes = vec![];
+        tablebase.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn entries_encoding() {
+        for outcome in [Outcome::Win, Outcome::Loss, Outcome::Draw] {
+            let entry = TableEntry {
+                outcome,
+                distance: 42,
+            };
+            assert_eq!(TableEntry::decode(entry.encode()), Some(entry));
+        }
+        assert_eq!(TableEntry::decode(3 << 14), None);
+    }
+
+    #[test]
+    fn build_solves_every_state() {
+        let tablebase = Tablebase::<Subtraction>::build();
+        assert_eq!(tablebase.len(), 2 * (MAX_PILE as usize + 1));
+        for state in Subtraction::states() {
+            // Losers take one token at a time, and winners get back to a multiple
+            // of MAX_TAKE + 1 with every move.
+            let (outcome, distance) = match state.pile % (MAX_TAKE + 1) {
+                0 => (Outcome::Loss, state.pile / (MAX_TAKE + 1) * 2),
+                _ => (Outcome::Win, state.pile / (MAX_TAKE + 1) * 2 + 1),
+            };
+            let expected = TableEntry {
+                outcome,
+                distance: distance as u16,
+            };
+            assert_eq!(tablebase.get(&state), Some(expected), "pile {}", state.pile);
+        }
+    }
+
+    #[test]
+    fn probes_utilities_and_actions() {
+        let tablebase = Tablebase::build();
+        let mut state = Subtraction::new(10);
+        assert_eq!(tablebase.best_action(&mut state), Some(2));
+        assert!(matches!(
+            tablebase.utility(&mut state),
+            Some(ExactUtility::Win(0))
+        ));
+
+        let mut state = Subtraction::new(12);
+        assert!(matches!(
+            tablebase.utility(&mut state),
+            Some(ExactUtility::Win(1))
+        ));
+        assert!(matches!(
+            tablebase.probe(&mut state),
+            Some((ExactUtility::Win(1), 6))
+        ));
+        assert_eq!(state.pile, 12);
+
+        assert!(tablebase.get(&Subtraction::new(MAX_PILE + 1)).is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        let tablebase = Tablebase::<Subtraction>::build();
+        let bytes = bytes(&tablebase);
+        assert_eq!(bytes.len(), 16 + 2 * tablebase.len());
+        let read = Tablebase::<Subtraction>::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.len(), tablebase.len());
+        for state in Subtraction::states() {
+            assert_eq!(read.get(&state), tablebase.get(&state));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        // Corrupted magic bytes, then an unknown version.
+        for (i, byte) in [(0, b'X'), (4, 2)] {
+            let mut bytes = bytes(&Tablebase::build());
+            bytes[i] = byte;
+            let error = Tablebase::<Subtraction>::read(&mut bytes.as_slice())
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let bytes = bytes(&Tablebase::build());
+        for len in [8, 16, bytes.len() - 1] {
+            let error = Tablebase::<Subtraction>::read(&mut &bytes[..len])
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! # Test games
+//! Small games with known solutions, shared by the tests of the crate.
+
+use crate::{
+    evaluator::TensorEncoding,
+    game::{ExactUtility, Game, Utility},
+    tablebase::Enumerable,
+};
+
+/// Largest number of tokens taken at once.
+pub const MAX_TAKE: u32 = 3;
+/// Largest pile of the states enumerated for tablebases.
+pub const MAX_PILE: u32 = 20;
+
+/// A subtraction game: players take turns removing between 1 and [MAX_TAKE] tokens
+/// from a pile, and whoever takes the last token wins.
+///
+/// Under perfect play, the side to move loses when the pile is a multiple of
+/// `MAX_TAKE + 1` and wins otherwise.
+#[derive(Clone, Debug)]
+pub struct Subtraction {
+    pub pile: u32,
+    pub player: u8,
+    history: Vec<u32>,
+}
+impl Subtraction {
+    pub fn new(pile: u32) -> Self {
+        Self {
+            pile,
+            player: 0,
+            history: vec![],
+        }
+    }
+}
+impl Game for Subtraction {
+    type Action = u32;
+    type ActionsIter = Vec<u32>;
+    type Hash = (u32, u8);
+    type Player = u8;
+
+    fn play(&mut self, action: &u32) {
+        self.pile -= action;
+        self.player = 1 - self.player;
+        self.history.push(*action);
+    }
+    fn undo(&mut self) {
+        self.pile += self.history.pop().unwrap();
+        self.player = 1 - self.player;
+    }
+
+    fn current_player(&self) -> u8 {
+        self.player
+    }
+    fn actions(&self) -> Vec<u32> {
+        (1..=self.pile.min(MAX_TAKE)).collect()
+    }
+
+    fn utility(&self) -> Utility<Self> {
+        if self.pile == 0 {
+            Utility::Exact(ExactUtility::Win(1 - self.player))
+        } else {
+            Utility::Unknown
+        }
+    }
+    fn hash(&self) -> (u32, u8) {
+        (self.pile, self.player)
+    }
+    fn action_key(&self, action: &u32) -> Option<u64> {
+        Some(*action as u64)
+    }
+}
+impl TensorEncoding for Subtraction {
+    const TENSOR_SIZE: usize = 2;
+    const POLICY_SIZE: usize = MAX_TAKE as usize;
+
+    fn to_tensor(&self) -> Vec<f32> {
+        vec![self.pile as f32, self.player as f32]
+    }
+    fn action_index(&self, action: &u32) -> usize {
+        *action as usize - 1
+    }
+}
+impl Enumerable for Subtraction {
+    type StatesIter = Vec<Self>;
+    type PredecessorsIter = Vec<Self>;
+
+    fn states() -> Vec<Self> {
+        (0..=MAX_PILE)
+            .flat_map(|pile| {
+                (0..2).map(move |player| Self {
+                    player,
+                    ..Self::new(pile)
+                })
+            })
+            .collect()
+    }
+    fn predecessors(&self) -> Vec<Self> {
+        (1..=MAX_TAKE)
+            .map(|take| self.pile + take)
+            .filter(|&pile| pile <= MAX_PILE)
+            .map(|pile| Self {
+                player: 1 - self.player,
+                ..Self::new(pile)
+            })
+            .collect()
+    }
+}
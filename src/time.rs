@@ -0,0 +1,286 @@
+//! # Time management
+//! Allocation of thinking time under clock-based time controls.
+//!
+//! A [TimeManager] turns the state of a player's [Clock] into a [TimeBudget]: a
+//! target time, usually spent on a move, and a maximum time the search may extend
+//! to when the choice of the best move is unclear. While searching, the manager
+//! decides from the [SearchProgress] whether to stop:
+//! - as soon as the best move is proven, or when the other moves could not catch up
+//!   with its number of visits in the remaining time.
+//! - once the target time is reached, unless the best move changed recently or the
+//!   two most visited moves are close, in which case the search goes on.
+//! - always once the maximum time is reached.
+//!
+//! See [MonteCarloTree::search](crate::mcts::MonteCarloTree::search) for the
+//! time-budgeted search using it.
+
+use std::time::Duration;
+
+/// Time control of a player for the next move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Clock {
+    /// Classical time control: the time remaining for the rest of the game, the
+    /// increment added after each move and the number of moves until the next time
+    /// control, if any.
+    Tournament {
+        remaining: Duration,
+        increment: Duration,
+        moves_to_go: Option<u32>,
+    },
+    /// A fixed time per move, like the play clock of general game playing matches.
+    PerMove(Duration),
+    /// A fixed number of search iterations per move, for matches which do not depend
+    /// on the speed of the hardware.
+    Iterations(u64),
+    /// No time limit.
+    Infinite,
+}
+
+/// Time allocated to a move by a [TimeManager].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeBudget {
+    /// Time the search should normally take.
+    pub target: Duration,
+    /// Time the search may extend to, and should never exceed.
+    pub maximum: Duration,
+}
+
+/// Progress of a search, used by [TimeManager::should_stop].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchProgress {
+    /// Time spent searching so far.
+    pub elapsed: Duration,
+    /// Number of iterations done so far.
+    pub iterations: u64,
+    /// Number of visits of the best root child.
+    pub best_visits: u32,
+    /// Highest number of visits among the other root children.
+    pub second_visits: u32,
+    /// Time at which the best root child last changed.
+    pub best_changed: Duration,
+    /// Whether the best move is proven to be optimal.
+    pub proven: bool,
+}
+
+/// Time allocation policy.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    /// Time kept aside on every move for communication and other overheads.
+    pub safety_margin: Duration,
+    /// Number of moves the remaining time is divided between, when the time control
+    /// does not give it.
+    pub default_moves_to_go: u32,
+    /// Maximum share of the remaining time that can be spent on a single move.
+    pub max_share: f32,
+    /// Factor of the target time the search can extend to.
+    pub max_extension: f32,
+    /// The search is extended when the best move changed during this last share of
+    /// the elapsed time.
+    pub instability_window: f32,
+    /// The search is extended when the second most visited move has at least this
+    /// share of the visits of the most visited one.
+    pub close_visits: f32,
+}
+impl Default for TimeManager {
+    fn default() -> Self {
+        Self {
+            safety_margin: Duration::from_millis(50),
+            default_moves_to_go: 30,
+            max_share: 0.5,
+            max_extension: 3f32,
+            instability_window: 0.25,
+            close_visits: 0.8,
+        }
+    }
+}
+
+impl TimeManager {
+    /// Allocates the time of the next move, or returns [None] for time controls
+    /// which are not limited by time.
+    pub fn allocate(&self, clock: &Clock) -> Option<TimeBudget> {
+        match *clock {
+            Clock::Tournament {
+                remaining,
+                increment,
+                moves_to_go,
+            } => {
+                let available = remaining.saturating_sub(self.safety_margin);
+                let moves = moves_to_go.unwrap_or(self.default_moves_to_go).max(1);
+                let maximum = available.mul_f32(self.max_share);
+                let target = (available / moves + increment).min(maximum);
+                let maximum = target.mul_f32(self.max_extension).min(maximum);
+                Some(TimeBudget { target, maximum })
+            }
+            Clock::PerMove(time) => {
+                let time = time.saturating_sub(self.safety_margin);
+                Some(TimeBudget {
+                    target: time,
+                    maximum: time,
+                })
+            }
+            Clock::Iterations(_) | Clock::Infinite => None,
+        }
+    }
+
+    /// Whether a search with the given budget should stop. Without budget, the
+    /// search only stops once the best move is proven.
+    pub fn should_stop(&self, budget: Option<&TimeBudget>, progress: &SearchProgress) -> bool {
+        if progress.proven {
+            return true;
+        }
+        let Some(budget) = budget else {
+            return false;
+        };
+        if progress.elapsed >= budget.maximum {
+            return true;
+        }
+
+        if progress.elapsed >= budget.target {
+            // Extend the search while the choice of the move is unclear.
+            let window = progress.elapsed.mul_f32(1f32 - self.instability_window);
+            let unstable = progress.best_changed > window;
+            let close =
+                progress.second_visits as f32 >= self.close_visits * progress.best_visits as f32;
+            return !unstable && !close;
+        }
+
+        // Stop early when no other move can catch up with the best one in the time
+        // left, assuming every remaining iteration goes to the second best.
+        let rate = progress.iterations as f32 / progress.elapsed.as_secs_f32().max(f32::EPSILON);
+        let left = (budget.target - progress.elapsed).as_secs_f32() * rate;
+        progress.best_visits.saturating_sub(progress.second_visits) as f32 > left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn manager() -> TimeManager {
+        TimeManager {
+            safety_margin: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    fn tournament(remaining: u64, increment: u64, moves_to_go: Option<u32>) -> Clock {
+        Clock::Tournament {
+            remaining: secs(remaining),
+            increment: secs(increment),
+            moves_to_go,
+        }
+    }
+
+    #[test]
+    fn allocates_tournament_time() {
+        let budget = |target, maximum| {
+            Some(TimeBudget {
+                target: secs(target),
+                maximum: secs(maximum),
+            })
+        };
+        let manager = manager();
+
+        // The remaining time is split between the default number of moves.
+        assert_eq!(manager.allocate(&tournament(60, 1, None)), budget(3, 9));
+        assert_eq!(
+            manager.allocate(&tournament(60, 1, Some(10))),
+            budget(7, 21)
+        );
+        // A single move never takes more than the maximum share of the time left.
+        assert_eq!(manager.allocate(&tournament(4, 10, Some(1))), budget(2, 2));
+    }
+
+    #[test]
+    fn allocates_other_clocks() {
+        let manager = TimeManager::default();
+        let time = secs(1) - manager.safety_margin;
+        assert_eq!(
+            manager.allocate(&Clock::PerMove(secs(1))),
+            Some(TimeBudget {
+                target: time,
+                maximum: time,
+            })
+        );
+        assert_eq!(manager.allocate(&Clock::Iterations(100)), None);
+        assert_eq!(manager.allocate(&Clock::Infinite), None);
+    }
+
+    #[test]
+    fn stops_on_proofs_and_at_the_maximum() {
+        let manager = manager();
+        let budget = TimeBudget {
+            target: secs(10),
+            maximum: secs(30),
+        };
+        let proven = SearchProgress {
+            proven: true,
+            ..Default::default()
+        };
+        assert!(manager.should_stop(None, &proven));
+        assert!(!manager.should_stop(None, &SearchProgress::default()));
+
+        // The choice is unclear, but the maximum time is reached.
+        let progress = SearchProgress {
+            elapsed: secs(30),
+            best_visits: 100,
+            second_visits: 100,
+            best_changed: secs(30),
+            ..Default::default()
+        };
+        assert!(manager.should_stop(Some(&budget), &progress));
+    }
+
+    #[test]
+    fn extends_unclear_searches() {
+        let manager = manager();
+        let budget = TimeBudget {
+            target: secs(10),
+            maximum: secs(30),
+        };
+        let clear = SearchProgress {
+            elapsed: secs(10),
+            iterations: 10000,
+            best_visits: 1000,
+            second_visits: 100,
+            best_changed: secs(1),
+            proven: false,
+        };
+        assert!(manager.should_stop(Some(&budget), &clear));
+
+        let unstable = SearchProgress {
+            best_changed: secs(9),
+            ..clear
+        };
+        assert!(!manager.should_stop(Some(&budget), &unstable));
+        let close = SearchProgress {
+            second_visits: 900,
+            ..clear
+        };
+        assert!(!manager.should_stop(Some(&budget), &close));
+    }
+
+    #[test]
+    fn stops_early_on_dominant_moves() {
+        let manager = manager();
+        let budget = TimeBudget {
+            target: secs(10),
+            maximum: secs(30),
+        };
+        // At a thousand iterations per second, 9000 are left before the target.
+        let progress = |best_visits| SearchProgress {
+            elapsed: secs(1),
+            iterations: 1000,
+            best_visits,
+            second_visits: 0,
+            best_changed: Duration::ZERO,
+            proven: false,
+        };
+        assert!(manager.should_stop(Some(&budget), &progress(10000)));
+        assert!(!manager.should_stop(Some(&budget), &progress(5000)));
+    }
+}
@@ -0,0 +1,414 @@
+//! # Tournaments
+//! Engine-versus-engine matches, to measure the strength of search changes.
+//!
+//! A [Tournament] plays a number of games between every pair of [Entrant]s of a
+//! two-player game, alternating which entrant moves first, on several threads.
+//! Results are reported as a win/draw/loss table along with the Elo difference of
+//! each pair and its 95% confidence interval.
+//!
+//! Matches can also stop early using a Sequential Probability Ratio Test ([Sprt]),
+//! which decides between two hypotheses on the Elo difference as soon as the games
+//! played are conclusive enough.
+
+use std::{
+    fmt::{self, Display},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    agent::{play_match, Agent},
+    alphabeta::{AlphaBeta, AlphaBetaConfig},
+    game::Game,
+    mcts::{MonteCarloTree, SearchConfig},
+    time::Clock,
+};
+
+// Quantile of the normal distribution used for confidence intervals (95%).
+const CONFIDENCE_QUANTILE: f32 = 1.96;
+
+/// A participant of a tournament.
+pub struct Entrant<G: Game> {
+    pub name: String,
+    // Creates the agent used for a single game, given the index of the game in its
+    // match.
+    new_agent: Arc<dyn Fn(u32) -> Box<dyn Agent<G>> + Send + Sync>,
+    // Clock overriding the one of the tournament.
+    clock: Option<Clock>,
+}
+impl<G: Game> Entrant<G> {
+    /// Creates an entrant using agents created by `new_agent`, a new one being
+    /// created for each game given the index of the game in its match.
+    pub fn new(
+        name: impl Into<String>,
+        new_agent: impl Fn(u32) -> Box<dyn Agent<G>> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            new_agent: Arc::new(new_agent),
+            clock: None,
+        }
+    }
+
+    /// Makes the entrant play with the given clock instead of the tournament's, to
+    /// compare searches given different numbers of iterations or handicaps.
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+}
+impl<G: Game + 'static> Entrant<G> {
+    /// Creates an entrant searching with a [MonteCarloTree] using the given
+    /// parameters, kept for the whole game. Each game uses its own
+    /// [stream](SearchConfig::stream) of the seed, so that seeded searches do not
+    /// replay the same game over and over.
+    pub fn mcts(name: impl Into<String>, config: SearchConfig) -> Self {
+        Self::new(name, move |game| {
+            Box::new(MonteCarloTree::with_config(config.stream(game as u64)))
+        })
+    }
+
+    /// Creates an entrant searching with an [AlphaBeta] using the given parameters,
+    /// kept for the whole game.
+    pub fn alphabeta(name: impl Into<String>, config: AlphaBetaConfig) -> Self {
+        Self::new(name, move |_| {
+            Box::new(AlphaBeta::with_config(config.clone()))
+        })
+    }
+}
+
+/// Parameters of a [Sequential Probability Ratio Test](https://en.wikipedia.org/wiki/Sequential_probability_ratio_test)
+/// between the hypotheses that the Elo difference of a match is `elo0` (H0) or
+/// `elo1` (H1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f32,
+    pub elo1: f32,
+    /// Probability of accepting H1 when H0 holds.
+    pub alpha: f32,
+    /// Probability of accepting H0 when H1 holds.
+    pub beta: f32,
+}
+impl Default for Sprt {
+    fn default() -> Self {
+        Self {
+            elo0: 0f32,
+            elo1: 10f32,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+/// Outcome of a [Sprt].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtDecision {
+    AcceptH0,
+    AcceptH1,
+}
+
+/// Parameters of a [Tournament].
+#[derive(Clone, Debug)]
+pub struct TournamentConfig {
+    /// Number of games played between each pair of entrants.
+    pub games: u32,
+    /// Number of games played in parallel.
+    pub threads: usize,
+    /// Time control of the entrants at the start of each game, unless they have
+    /// their own. Defaults to a thousand iterations per move, so that games end
+    /// whatever the game.
+    pub clock: Clock,
+    /// Maximum number of plies of a game, after which it is scored as a draw.
+    pub max_plies: Option<u32>,
+    /// Stops each match early once the test is conclusive.
+    pub sprt: Option<Sprt>,
+}
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        Self {
+            games: 100,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+            clock: Clock::Iterations(1000),
+            max_plies: None,
+            sprt: None,
+        }
+    }
+}
+
+/// Results of the games between two entrants, from the point of view of the first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MatchResult {
+    /// Indices of the entrants in the tournament.
+    pub first: usize,
+    pub second: usize,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Decision of the SPRT, if the match was stopped by it.
+    pub sprt: Option<SprtDecision>,
+}
+impl MatchResult {
+    /// Number of games played.
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Average score of the first entrant, a win counting as 1 and a draw as 0.5.
+    pub fn score(&self) -> f32 {
+        (self.wins as f32 + 0.5 * self.draws as f32) / self.games().max(1) as f32
+    }
+
+    /// Elo difference between the first and the second entrants, along with the
+    /// margin of its 95% confidence interval.
+    ///
+    /// Returns [None] if an entrant scored every point, the difference being
+    /// infinite. Bounds of the interval are kept within the scores of half a point
+    /// and all points but half a point.
+    pub fn elo(&self) -> Option<(f32, f32)> {
+        let score = self.score();
+        if score <= 0f32 || score >= 1f32 {
+            return None;
+        }
+        let games = self.games() as f32;
+        let margin = CONFIDENCE_QUANTILE * (self.variance() / games).sqrt();
+        let low = elo((score - margin).max(0.5 / games));
+        let high = elo((score + margin).min(1f32 - 0.5 / games));
+        Some((elo(score), (high - low) / 2f32))
+    }
+
+    /// Log-likelihood ratio of the given test, using the normal approximation of
+    /// the distribution of scores.
+    pub fn llr(&self, sprt: &Sprt) -> f32 {
+        let variance = self.variance();
+        if variance <= 0f32 {
+            return 0f32;
+        }
+        let (score0, score1) = (expected_score(sprt.elo0), expected_score(sprt.elo1));
+        self.games() as f32 * (score1 - score0) * (2f32 * self.score() - score0 - score1)
+            / (2f32 * variance)
+    }
+
+    // Variance of the score of a single game.
+    fn variance(&self) -> f32 {
+        let score = self.score();
+        (self.wins as f32 * (1f32 - score).powi(2)
+            + self.draws as f32 * (0.5 - score).powi(2)
+            + self.losses as f32 * score.powi(2))
+            / self.games().max(1) as f32
+    }
+
+    // Decision of the test given the games played so far, if any.
+    fn decide(&self, sprt: &Sprt) -> Option<SprtDecision> {
+        let llr = self.llr(sprt);
+        if llr >= ((1f32 - sprt.beta) / sprt.alpha).ln() {
+            Some(SprtDecision::AcceptH1)
+        } else if llr <= (sprt.beta / (1f32 - sprt.alpha)).ln() {
+            Some(SprtDecision::AcceptH0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Results of a [Tournament], displayed as a table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TournamentResult {
+    /// Names of the entrants.
+    pub names: Vec<String>,
+    /// Results of every pair of entrants.
+    pub matches: Vec<MatchResult>,
+}
+impl Display for TournamentResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.names.iter().map(|name| name.len()).fold(8, usize::max);
+        writeln!(
+            f,
+            "{:<width$}    {:<width$}  {:>6} {:>6} {:>6}  {:>6}  {:>16}",
+            "Entrant", "Opponent", "W", "D", "L", "Score", "Elo"
+        )?;
+        for result in &self.matches {
+            let elo = match result.elo() {
+                Some((elo, margin)) => format!("{elo:>+7.1} ± {margin:<6.1}"),
+                None => format!("{:>7}   {:<6}", "n/a", ""),
+            };
+            write!(
+                f,
+                "{:<width$} vs {:<width$}  {:>6} {:>6} {:>6}  {:>5.1}%  {}",
+                self.names[result.first],
+                self.names[result.second],
+                result.wins,
+                result.draws,
+                result.losses,
+                100f32 * result.score(),
+                elo
+            )?;
+            match result.sprt {
+                Some(SprtDecision::AcceptH0) => writeln!(f, "  SPRT: H0 accepted")?,
+                Some(SprtDecision::AcceptH1) => writeln!(f, "  SPRT: H1 accepted")?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A round-robin tournament between entrants of a two-player game.
+pub struct Tournament<G: Game> {
+    config: TournamentConfig,
+    entrants: Vec<Entrant<G>>,
+}
+impl<G: Game> Tournament<G> {
+    /// Creates a tournament between the given entrants.
+    pub fn new(config: TournamentConfig, entrants: Vec<Entrant<G>>) -> Self {
+        Self { config, entrants }
+    }
+
+    /// Plays every match of the tournament, starting games from states created by
+    /// `new_game`.
+    pub fn run(&self, new_game: impl Fn() -> G + Sync) -> TournamentResult {
+        let mut matches = vec![];
+        for first in 0..self.entrants.len() {
+            for second in first + 1..self.entrants.len() {
+                matches.push(self.play_match(first, second, &new_game));
+            }
+        }
+        TournamentResult {
+            names: self.entrants.iter().map(|e| e.name.clone()).collect(),
+            matches,
+        }
+    }
+
+    /// Plays the games between two entrants, alternating which one moves first.
+    fn play_match(
+        &self,
+        first: usize,
+        second: usize,
+        new_game: &(impl Fn() -> G + Sync),
+    ) -> MatchResult {
+        let result = Mutex::new(MatchResult {
+            first,
+            second,
+            ..Default::default()
+        });
+        let next_game = AtomicU32::new(0);
+        let stop = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            for _ in 0..self.config.threads.max(1) {
+                scope.spawn(|| loop {
+                    let game = next_game.fetch_add(1, Ordering::Relaxed);
+                    if game >= self.config.games || stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    let seats = if game.is_multiple_of(2) {
+                        [first, second]
+                    } else {
+                        [second, first]
+                    };
+                    let winner = self.play_game(game, seats, new_game());
+
+                    let mut result = result.lock().unwrap();
+                    match winner.map(|seat| seats[seat]) {
+                        Some(entrant) if entrant == first => result.wins += 1,
+                        Some(_) => result.losses += 1,
+                        None => result.draws += 1,
+                    }
+                    if let Some(sprt) = &self.config.sprt {
+                        if result.sprt.is_none() {
+                            result.sprt = result.decide(sprt);
+                        }
+                        if result.sprt.is_some() {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        result.into_inner().unwrap()
+    }
+
+    /// Plays a game between the entrants of the given seats, the first seat being
+    /// the player to move in the initial state. Returns the seat of the winner, or
+    /// [None] for a draw, see [play_match].
+    fn play_game(&self, game: u32, seats: [usize; 2], mut state: G) -> Option<usize> {
+        let entrants = seats.map(|entrant| &self.entrants[entrant]);
+        let [mut first, mut second] = entrants.map(|entrant| (entrant.new_agent)(game));
+        let clocks = entrants.map(|entrant| entrant.clock.unwrap_or(self.config.clock));
+        play_match(
+            &mut state,
+            &mut [first.as_mut(), second.as_mut()],
+            &clocks,
+            self.config.max_plies,
+        )
+        .winner
+    }
+}
+
+/// Elo difference corresponding to an average score.
+fn elo(score: f32) -> f32 {
+    -400f32 * (1f32 / score - 1f32).log10()
+}
+
+/// Average score expected for a given Elo difference.
+fn expected_score(elo: f32) -> f32 {
+    1f32 / (1f32 + 10f32.powf(-elo / 400f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Subtraction;
+
+    fn result(wins: u32, draws: u32, losses: u32) -> MatchResult {
+        MatchResult {
+            first: 0,
+            second: 1,
+            wins,
+            draws,
+            losses,
+            sprt: None,
+        }
+    }
+
+    #[test]
+    fn elo_of_even_match() {
+        let (elo, margin) = result(10, 0, 10).elo().unwrap();
+        assert!(elo.abs() < 1e-3);
+        assert!(margin.is_finite() && margin > 0f32);
+    }
+
+    #[test]
+    fn elo_of_one_sided_match() {
+        assert!(result(20, 0, 0).elo().is_none());
+        assert!(result(0, 0, 20).elo().is_none());
+        assert!(result(0, 0, 0).elo().is_none());
+
+        let (elo, margin) = result(19, 1, 0).elo().unwrap();
+        assert!(elo > 0f32 && elo.is_finite());
+        assert!(margin.is_finite());
+    }
+
+    #[test]
+    fn default_tournament_ends() {
+        let config = TournamentConfig {
+            games: 4,
+            ..Default::default()
+        };
+        let search = SearchConfig {
+            seed: Some(0),
+            ..Default::default()
+        };
+        let entrants = vec![
+            Entrant::mcts("mcts", search),
+            Entrant::alphabeta("alphabeta", AlphaBetaConfig::default()),
+        ];
+        let result = Tournament::new(config, entrants).run(|| Subtraction::new(30));
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].games(), 4);
+    }
+}